@@ -0,0 +1,375 @@
+use super::DatabaseError;
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Environment variable overriding every other password source.
+const PASSWORD_ENV: &str = "DPR_DB_PASSWORD";
+
+/// Option file keys holding numbers rather than strings.
+const NUMERIC_OPTIONS: [&str; 4] = ["port", "pool_min", "pool_max", "connect_timeout"];
+
+/// Environment variable naming the config file when `--config` is not given.
+const CONFIG_ENV: &str = "DPR_CONFIG";
+
+/// Layout of the config file: connection settings in `[client]` and any
+/// number of alternatives in `[profile.<name>]` sections.
+#[derive(Deserialize)]
+struct ConfigFile {
+    client: Option<DatabaseClient>,
+    #[serde(default)]
+    profile: HashMap<String, DatabaseClient>,
+}
+
+pub(crate) struct DatabaseConfig {
+    client: DatabaseClient,
+}
+
+#[derive(Deserialize)]
+struct DatabaseClient {
+    #[serde(default)]
+    backend: DatabaseBackend,
+    host: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    /// File holding the password, relative to the config file.
+    password_file: Option<PathBuf>,
+    /// MySQL option file whose `[client]` section provides the settings missing
+    /// from this config, `~/.my.cnf` for MySQL if not given.
+    defaults_file: Option<PathBuf>,
+    /// TCP port, the backend's default port if not given.
+    port: Option<u16>,
+    /// Unix socket to connect through instead of TCP. PostgreSQL expects the
+    /// directory holding the socket.
+    socket: Option<PathBuf>,
+    /// Database to use, `{user}_dpemr_experiments` if not given.
+    database: Option<String>,
+    #[serde(alias = "ssl-mode")]
+    ssl_mode: Option<SslMode>,
+    /// CA certificate (PEM) used to verify the server.
+    #[serde(alias = "ssl-ca")]
+    ssl_ca: Option<PathBuf>,
+    pool_min: Option<usize>,
+    pool_max: Option<usize>,
+    /// Seconds to wait for a new connection.
+    #[serde(alias = "connect-timeout")]
+    connect_timeout: Option<u64>,
+    /// Database file for the SQLite backend, relative to the config file.
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DatabaseBackend {
+    #[default]
+    MySql,
+    Postgres,
+    Sqlite,
+    /// Keeps the table in the memory of a single `dpr` process, mostly useful
+    /// for testing.
+    Memory,
+}
+
+/// TLS modes, named after MySQL's `ssl-mode` option.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SslMode {
+    #[default]
+    #[serde(alias = "DISABLED")]
+    Disabled,
+    /// Encrypt the connection without verifying the server certificate.
+    #[serde(alias = "REQUIRED")]
+    Required,
+    /// Verify the server certificate but not its host name.
+    #[serde(alias = "VERIFY_CA")]
+    VerifyCa,
+    /// Verify the server certificate and its host name.
+    #[serde(alias = "VERIFY_IDENTITY")]
+    VerifyIdentity,
+}
+
+impl DatabaseConfig {
+    /// Looks for the config file in `DPR_CONFIG`, then
+    /// `$XDG_CONFIG_HOME/dpr/config.toml` (`~/.config` if unset) and finally
+    /// `~/.dpr.toml`.
+    pub(crate) fn find_config_file() -> Result<PathBuf> {
+        if let Some(config) = env::var_os(CONFIG_ENV) {
+            return Ok(PathBuf::from(config));
+        }
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let xdg_config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(".config")));
+        [
+            xdg_config.map(|dir| dir.join("dpr").join("config.toml")),
+            home.map(|home| home.join(".dpr.toml")),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|config| config.is_file())
+        .ok_or_else(|| DatabaseError::NoConfigFile.into())
+    }
+
+    /// Reads the `[client]` section, or the given profile, of the config file,
+    /// completes it from a MySQL option file and resolves the password from
+    /// its sources, in order of precedence: `DPR_DB_PASSWORD`,
+    /// `password_file`, `password` and the option file.
+    pub(crate) async fn from_config_file(
+        file_name: &Path,
+        profile: Option<&str>,
+        defaults_file: Option<&Path>,
+    ) -> Result<DatabaseConfig> {
+        let file_contents = String::from_utf8(tokio::fs::read(file_name).await?)?;
+        let mut config_file: ConfigFile = toml::from_str(&file_contents)?;
+        let mut dbc = DatabaseConfig {
+            client: match profile {
+                Some(profile) => config_file
+                    .profile
+                    .remove(profile)
+                    .ok_or_else(|| DatabaseError::UnknownProfile(profile.to_owned()))?,
+                None => config_file.client.ok_or(DatabaseError::MissingClient)?,
+            },
+        };
+        let client = &mut dbc.client;
+        if client.password.is_some() {
+            warn_if_readable_by_others(file_name).await?;
+        }
+        if let Some(config_dir) = file_name.parent() {
+            for path in [
+                client.path.as_mut(),
+                client.ssl_ca.as_mut(),
+                client.password_file.as_mut(),
+                client.defaults_file.as_mut(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                if path.is_relative() {
+                    *path = config_dir.join(&path);
+                }
+            }
+        }
+        // `~/.my.cnf` is shared with the other MySQL clients, so values only
+        // they understand are skipped rather than refused.
+        let (defaults_file, implicit) = match defaults_file.or(client.defaults_file.as_deref()) {
+            Some(defaults_file) => (Some(defaults_file.to_path_buf()), false),
+            None => (
+                env::var_os("HOME")
+                    .map(|home| Path::new(&home).join(".my.cnf"))
+                    .filter(|my_cnf| {
+                        matches!(client.backend, DatabaseBackend::MySql) && my_cnf.is_file()
+                    }),
+                true,
+            ),
+        };
+        if let Some(defaults_file) = defaults_file {
+            let defaults = DatabaseClient::from_option_file(&defaults_file, implicit).await?;
+            if client.password.is_none() && defaults.password.is_some() {
+                warn_if_readable_by_others(&defaults_file).await?;
+            }
+            client.fill_from(defaults);
+        }
+        if let Some(password_file) = &client.password_file {
+            warn_if_readable_by_others(password_file).await?;
+            let password = String::from_utf8(tokio::fs::read(password_file).await?)?;
+            client.password = Some(password.trim_end_matches(['\r', '\n']).to_owned());
+        }
+        if let Ok(password) = env::var(PASSWORD_ENV) {
+            client.password = Some(password);
+        }
+        Ok(dbc)
+    }
+
+    pub(crate) fn get_backend(&self) -> DatabaseBackend {
+        self.client.backend
+    }
+
+    pub(crate) fn get_user(&self) -> Result<&str> {
+        self.client
+            .user
+            .as_deref()
+            .ok_or_else(|| DatabaseError::MissingKey("user").into())
+    }
+
+    pub(crate) fn get_password(&self) -> Result<&str> {
+        self.client
+            .password
+            .as_deref()
+            .ok_or_else(|| DatabaseError::MissingKey("password").into())
+    }
+
+    pub(crate) fn get_host(&self) -> Result<&str> {
+        self.client
+            .host
+            .as_deref()
+            .ok_or_else(|| DatabaseError::MissingKey("host").into())
+    }
+
+    pub(crate) fn get_port(&self) -> Option<u16> {
+        self.client.port
+    }
+
+    pub(crate) fn get_socket(&self) -> Option<&Path> {
+        self.client.socket.as_deref()
+    }
+
+    pub(crate) fn get_database(&self) -> Result<String> {
+        match &self.client.database {
+            Some(database) => Ok(database.clone()),
+            None => Ok(format!("{}_dpemr_experiments", self.get_user()?)),
+        }
+    }
+
+    pub(crate) fn get_ssl_mode(&self) -> SslMode {
+        self.client.ssl_mode.unwrap_or_default()
+    }
+
+    pub(crate) fn get_ssl_ca(&self) -> Option<&Path> {
+        self.client.ssl_ca.as_deref()
+    }
+
+    pub(crate) fn get_pool_min(&self) -> Option<usize> {
+        self.client.pool_min
+    }
+
+    pub(crate) fn get_pool_max(&self) -> Option<usize> {
+        self.client.pool_max
+    }
+
+    pub(crate) fn get_connect_timeout(&self) -> Option<Duration> {
+        self.client.connect_timeout.map(Duration::from_secs)
+    }
+
+    pub(crate) fn get_path(&self) -> Result<&Path> {
+        self.client
+            .path
+            .as_deref()
+            .ok_or_else(|| DatabaseError::MissingKey("path").into())
+    }
+}
+
+impl DatabaseClient {
+    /// Reads the `[client]` section of a MySQL option file such as `~/.my.cnf`.
+    /// Dashes and underscores are interchangeable in option names, as in MySQL.
+    /// Options with values `dpr` does not support are an error, unless
+    /// `skip_invalid` is set, in which case they are left out with a warning.
+    async fn from_option_file(file_name: &Path, skip_invalid: bool) -> Result<DatabaseClient> {
+        let file_contents = String::from_utf8(tokio::fs::read(file_name).await?)?;
+        let mut options = toml::Table::new();
+        let mut section = "";
+        for line in file_contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(['#', ';', '!']) {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+            if section != "client" {
+                continue;
+            }
+            let (key, value) = line.split_once('=').unwrap_or((line, ""));
+            let key = key.trim().replace('-', "_");
+            let value = value.trim();
+            let value = [('"', '"'), ('\'', '\'')]
+                .iter()
+                .find_map(|&(open, close)| value.strip_prefix(open)?.strip_suffix(close))
+                .unwrap_or(value);
+            let value = match value.parse() {
+                Ok(number) if NUMERIC_OPTIONS.contains(&key.as_str()) => {
+                    toml::Value::Integer(number)
+                }
+                _ => toml::Value::String(value.to_owned()),
+            };
+            if skip_invalid {
+                let option = toml::Table::from_iter([(key.clone(), value.clone())]);
+                if option.try_into::<DatabaseClient>().is_err() {
+                    eprintln!(
+                        "dpr: ignoring `{}` in {}, its value is not supported",
+                        key,
+                        file_name.display()
+                    );
+                    continue;
+                }
+            }
+            options.insert(key, value);
+        }
+        Ok(options.try_into()?)
+    }
+
+    /// Takes the connection settings of `defaults` which are not set here.
+    fn fill_from(&mut self, defaults: DatabaseClient) {
+        self.host = self.host.take().or(defaults.host);
+        self.user = self.user.take().or(defaults.user);
+        self.password = self.password.take().or(defaults.password);
+        self.port = self.port.or(defaults.port);
+        self.socket = self.socket.take().or(defaults.socket);
+        self.database = self.database.take().or(defaults.database);
+        self.ssl_mode = self.ssl_mode.or(defaults.ssl_mode);
+        self.ssl_ca = self.ssl_ca.take().or(defaults.ssl_ca);
+        self.connect_timeout = self.connect_timeout.or(defaults.connect_timeout);
+        self.pool_min = self.pool_min.or(defaults.pool_min);
+        self.pool_max = self.pool_max.or(defaults.pool_max);
+    }
+}
+
+/// Passwords should not be readable by the other users of a shared machine.
+#[cfg(unix)]
+async fn warn_if_readable_by_others(file_name: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = tokio::fs::metadata(file_name).await?.permissions().mode();
+    if mode & 0o044 != 0 {
+        eprintln!(
+            "dpr: {} holds a database password but can be read by group or others, restrict it with `chmod 600`",
+            file_name.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn warn_if_readable_by_others(_file_name: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn skips_unsupported_options_only_if_asked() {
+        let option_file = env::temp_dir().join(format!("dpr-{}-my.cnf", std::process::id()));
+        tokio::fs::write(
+            &option_file,
+            "[mysql]\nauto-rehash\n\n[client]\nuser = alice\nport = 3307\nssl-mode = PREFERRED\n",
+        )
+        .await
+        .unwrap();
+        let client = DatabaseClient::from_option_file(&option_file, true)
+            .await
+            .unwrap();
+        assert_eq!(client.user.as_deref(), Some("alice"));
+        assert_eq!(client.port, Some(3307));
+        assert_eq!(client.ssl_mode, None);
+        assert!(DatabaseClient::from_option_file(&option_file, false)
+            .await
+            .is_err());
+        tokio::fs::remove_file(option_file).await.unwrap();
+    }
+
+    #[test]
+    fn takes_missing_pool_sizes_from_defaults() {
+        let mut client: DatabaseClient = toml::from_str("user = \"alice\"\npool_max = 4").unwrap();
+        let defaults: DatabaseClient = toml::from_str("pool_min = 2\npool_max = 8").unwrap();
+        client.fill_from(defaults);
+        assert_eq!(client.pool_min, Some(2));
+        assert_eq!(client.pool_max, Some(4));
+    }
+}
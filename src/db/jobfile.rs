@@ -0,0 +1,301 @@
+use super::DatabaseError;
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::{path::Path, str::FromStr};
+
+/// Format of a file of jobs to load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum JobFormat {
+    /// One command per line, with `#key: value` directives.
+    Text,
+    /// A header line naming the fields, then one record per line.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+    /// One `[[job]]` table per record.
+    Toml,
+}
+
+impl FromStr for JobFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(JobFormat::Text),
+            "csv" => Ok(JobFormat::Csv),
+            "jsonl" => Ok(JobFormat::Jsonl),
+            "toml" => Ok(JobFormat::Toml),
+            _ => Err(DatabaseError::UnknownFormat(s.to_owned()).into()),
+        }
+    }
+}
+
+impl JobFormat {
+    /// Format of a file going by its extension, plain text unless it is
+    /// `.csv`, `.jsonl` or `.toml`.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => JobFormat::Csv,
+            Some("jsonl") => JobFormat::Jsonl,
+            Some("toml") => JobFormat::Toml,
+            _ => JobFormat::Text,
+        }
+    }
+}
+
+/// A job of a structured job file. Its other fields are kept as the keys and
+/// values of the directives they stand for, lists joined with commas.
+#[derive(Default)]
+pub(super) struct Record {
+    pub(super) command: Option<String>,
+    pub(super) cwd: Option<String>,
+    pub(super) env: Vec<(String, String)>,
+    pub(super) attributes: Vec<(String, String)>,
+}
+
+impl Record {
+    fn set(&mut self, key: &str, value: String) {
+        match key {
+            "command" => self.command = Some(value),
+            "cwd" => self.cwd = Some(value),
+            _ => self.attributes.push((key.to_owned(), value)),
+        }
+    }
+
+    fn set_env(&mut self, name: &str, value: String) -> Result<()> {
+        if name.is_empty() || name.contains(['=', '\0']) {
+            return Err(DatabaseError::InvalidEnvName(name.to_owned()).into());
+        }
+        self.env.push((name.to_owned(), value));
+        Ok(())
+    }
+}
+
+/// Reads the records of a structured job file.
+pub(super) fn parse_records(file_contents: &str, format: JobFormat) -> Result<Vec<Record>> {
+    match format {
+        JobFormat::Text => unreachable!("plain text files have no records"),
+        JobFormat::Csv => parse_csv(file_contents),
+        JobFormat::Jsonl => parse_jsonl(file_contents),
+        JobFormat::Toml => parse_toml(file_contents),
+    }
+}
+
+/// Columns are named after the fields, and `env.NAME` columns give the
+/// environment variable `NAME`. Empty cells leave a field unset.
+fn parse_csv(file_contents: &str) -> Result<Vec<Record>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file_contents.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut records = vec![];
+    for row in reader.records() {
+        let mut record = Record::default();
+        for (key, value) in headers.iter().zip(row?.iter()) {
+            if value.is_empty() {
+                continue;
+            }
+            match key.strip_prefix("env.") {
+                Some(name) => record.set_env(name, value.to_owned())?,
+                None => record.set(key, value.to_owned()),
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Blank lines are skipped, and `env` is an object of variables.
+fn parse_jsonl(file_contents: &str) -> Result<Vec<Record>> {
+    let mut records = vec![];
+    for (i, line) in file_contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+            .map_err(|e| DatabaseError::InvalidRecord(i + 1, e.to_string()))?;
+        let mut record = Record::default();
+        for (key, value) in object {
+            match (key.as_str(), value) {
+                ("env", serde_json::Value::Object(env)) => {
+                    for (name, value) in env {
+                        let value = json_scalar(value)
+                            .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                        record.set_env(&name, value)?;
+                    }
+                }
+                (_, serde_json::Value::Null) => {}
+                ("env", _) => return Err(DatabaseError::InvalidField(key).into()),
+                (_, serde_json::Value::Array(items)) => {
+                    let items = items
+                        .into_iter()
+                        .map(json_scalar)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                    record.set(&key, items.join(","));
+                }
+                (_, value) => {
+                    let value = json_scalar(value)
+                        .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                    record.set(&key, value);
+                }
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn json_scalar(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlJobFile {
+    #[serde(default)]
+    job: Vec<toml::Table>,
+}
+
+/// Every `[[job]]` table is a record, and `env` a table of variables.
+fn parse_toml(file_contents: &str) -> Result<Vec<Record>> {
+    let file: TomlJobFile = toml::from_str(file_contents)?;
+    let mut records = vec![];
+    for table in file.job {
+        let mut record = Record::default();
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("env", toml::Value::Table(env)) => {
+                    for (name, value) in env {
+                        let value = toml_scalar(value)
+                            .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                        record.set_env(&name, value)?;
+                    }
+                }
+                ("env", _) => return Err(DatabaseError::InvalidField(key).into()),
+                (_, toml::Value::Array(items)) => {
+                    let items = items
+                        .into_iter()
+                        .map(toml_scalar)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                    record.set(&key, items.join(","));
+                }
+                (_, value) => {
+                    let value = toml_scalar(value)
+                        .ok_or_else(|| DatabaseError::InvalidField(key.clone()))?;
+                    record.set(&key, value);
+                }
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn toml_scalar(value: toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(result: Result<Vec<Record>>) -> DatabaseError {
+        match result {
+            Ok(_) => panic!("the job file was accepted"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    #[test]
+    fn reads_csv_records() {
+        let records = parse_records(
+            "command, cpus, env.SEED\necho a, 2, 1\necho b,,\n",
+            JobFormat::Csv,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].command.as_deref(), Some("echo a"));
+        assert_eq!(records[0].attributes, [("cpus".to_owned(), "2".to_owned())]);
+        assert_eq!(records[0].env, [("SEED".to_owned(), "1".to_owned())]);
+        assert!(records[1].attributes.is_empty() && records[1].env.is_empty());
+    }
+
+    #[test]
+    fn leaves_the_command_unset_without_a_command_column() {
+        let records = parse_records("cpus,mem\n2,4G\n", JobFormat::Csv).unwrap();
+        assert_eq!(records[0].command, None);
+        assert!(matches!(
+            error(parse_records("command,env.\necho a,1\n", JobFormat::Csv)),
+            DatabaseError::InvalidEnvName(name) if name.is_empty()
+        ));
+    }
+
+    #[test]
+    fn reads_jsonl_records() {
+        let records = parse_records(
+            "{\"command\": \"echo a\", \"tags\": [\"x\", 1], \"env\": {\"SEED\": 3}}\n\n",
+            JobFormat::Jsonl,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].attributes,
+            [("tags".to_owned(), "x,1".to_owned())]
+        );
+        assert_eq!(records[0].env, [("SEED".to_owned(), "3".to_owned())]);
+        assert!(matches!(
+            error(parse_records(
+                "{\"command\": \"echo a\"}\n{\"command\"\n",
+                JobFormat::Jsonl
+            )),
+            DatabaseError::InvalidRecord(2, _)
+        ));
+        assert!(matches!(
+            error(parse_records("{\"command\": \"echo a\", \"env\": 1}\n", JobFormat::Jsonl)),
+            DatabaseError::InvalidField(key) if key == "env"
+        ));
+    }
+
+    #[test]
+    fn reads_toml_records() {
+        let records = parse_records(
+            "[[job]]\ncommand = \"echo a\"\ntimeout = \"1m\"\n\n[[job]]\ncpus = 2\n",
+            JobFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].attributes,
+            [("timeout".to_owned(), "1m".to_owned())]
+        );
+        assert_eq!(records[1].command, None);
+        assert!(matches!(
+            error(parse_records("[[job]]\ncommand = \"echo a\"\nenv = [1]\n", JobFormat::Toml)),
+            DatabaseError::InvalidField(key) if key == "env"
+        ));
+        assert!(parse_records("[[jobs]]\ncommand = \"echo a\"\n", JobFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn picks_the_format_from_the_name_or_extension() {
+        assert_eq!("csv".parse::<JobFormat>().unwrap(), JobFormat::Csv);
+        assert!("yaml".parse::<JobFormat>().is_err());
+        assert_eq!(
+            JobFormat::from_path(Path::new("jobs.toml")),
+            JobFormat::Toml
+        );
+        assert_eq!(JobFormat::from_path(Path::new("jobs.txt")), JobFormat::Text);
+    }
+}
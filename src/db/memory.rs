@@ -0,0 +1,502 @@
+use super::{
+    new_claim_token, Capacity, Dependency, ExperimentStatus, Job, JobInfo, JobSelection, JobStore,
+    RunStats, TableEntry, TagFilter, FAILED_STATUSES, SCHEMA_VERSION,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rand::{seq::SliceRandom, thread_rng};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// In-memory storage for a single experiment table. The table only lives as
+/// long as the `dpr` process, so this is meant for tests and dry runs rather
+/// than for sharing work between runners.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryDatabase {
+    jobs: Mutex<Vec<MemoryJob>>,
+    settings: Mutex<HashMap<String, String>>,
+    /// Pairs of jobs and the jobs they depend on. Locked after `jobs` when
+    /// both are needed.
+    dependencies: Mutex<Vec<(usize, usize)>>,
+    /// Leases of the workers, as the time of their last heartbeat plus the
+    /// lease.
+    workers: Mutex<HashMap<String, Instant>>,
+}
+
+#[derive(Debug)]
+struct MemoryJob {
+    id: usize,
+    command: Arc<String>,
+    status: ExperimentStatus,
+    timeout: Option<Duration>,
+    timeout_count: usize,
+    cpus: usize,
+    mem: u64,
+    priority: i32,
+    label: Option<String>,
+    tags: Vec<String>,
+    params: Vec<(String, String)>,
+    cwd: Option<String>,
+    env: Vec<(String, String)>,
+    content_hash: String,
+    run: RunStats,
+    claim_token: Option<Arc<String>>,
+    worker_id: Option<String>,
+    lost_count: usize,
+    attempts: usize,
+    retry_after: Option<Instant>,
+}
+
+impl MemoryDatabase {
+    fn jobs(&self) -> MutexGuard<'_, Vec<MemoryJob>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn dependencies(&self) -> MutexGuard<'_, Vec<(usize, usize)>> {
+        self.dependencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn settings(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.settings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn workers(&self) -> MutexGuard<'_, HashMap<String, Instant>> {
+        self.workers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Changes the job with the given id if it is still running under the
+    /// given claim, and returns whether it was.
+    fn update_claimed_job(
+        &self,
+        id: usize,
+        claim_token: &str,
+        update: impl FnOnce(&mut MemoryJob),
+    ) -> bool {
+        let mut jobs = self.jobs();
+        let claimed = jobs.iter_mut().find(|j| {
+            j.id == id
+                && j.status == ExperimentStatus::Running
+                && j.claim_token.as_deref().map(String::as_str) == Some(claim_token)
+        });
+        claimed.map(update).is_some()
+    }
+}
+
+#[async_trait]
+impl JobStore for MemoryDatabase {
+    async fn create_table(&self, _version: usize) -> Result<()> {
+        self.jobs().clear();
+        self.dependencies().clear();
+        self.settings().clear();
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<usize> {
+        Ok(SCHEMA_VERSION)
+    }
+
+    async fn migrate(&self, _from: usize) -> Result<()> {
+        Ok(())
+    }
+
+    async fn insert_jobs(&self, table_entries: Vec<TableEntry>) -> Result<Vec<usize>> {
+        let mut jobs = self.jobs();
+        let mut ids = Vec::with_capacity(table_entries.len());
+        for t in table_entries {
+            let id = jobs.len() + 1;
+            jobs.push(MemoryJob {
+                content_hash: t.content_hash(),
+                id,
+                command: Arc::new(t.command),
+                status: t.status,
+                timeout: t.timeout,
+                timeout_count: 0,
+                cpus: t.cpus,
+                mem: t.mem,
+                priority: t.priority,
+                label: t.label,
+                tags: t.tags,
+                params: t.params,
+                cwd: t.cwd,
+                env: t.env,
+                run: RunStats::default(),
+                claim_token: None,
+                worker_id: None,
+                lost_count: 0,
+                attempts: 0,
+                retry_after: None,
+            });
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    async fn insert_commands(&self, commands: Vec<String>) -> Result<()> {
+        let table_entries = commands
+            .into_iter()
+            .map(|command| TableEntry {
+                command,
+                status: ExperimentStatus::NotRunning,
+                timeout: None,
+                cpus: 1,
+                mem: 0,
+                priority: 0,
+                label: None,
+                after: vec![],
+                tags: vec![],
+                params: vec![],
+                cwd: None,
+                env: vec![],
+                copy: 0,
+            })
+            .collect();
+        self.insert_jobs(table_entries).await?;
+        Ok(())
+    }
+
+    async fn get_content_hashes(&self) -> Result<Vec<(String, usize, ExperimentStatus)>> {
+        Ok(self
+            .jobs()
+            .iter()
+            .map(|j| (j.content_hash.clone(), j.id, j.status))
+            .collect())
+    }
+
+    async fn find_jobs(&self, dependency: &Dependency) -> Result<Vec<usize>> {
+        Ok(self
+            .jobs()
+            .iter()
+            .filter(|j| match dependency {
+                Dependency::Id(id) => j.id == *id,
+                Dependency::Label(label) => j.label.as_ref() == Some(label),
+            })
+            .map(|j| j.id)
+            .collect())
+    }
+
+    async fn insert_dependencies(&self, dependencies: Vec<(usize, usize)>) -> Result<()> {
+        self.dependencies().extend(dependencies);
+        Ok(())
+    }
+
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let mut jobs = self.jobs();
+        let dependencies = self.dependencies();
+        let has_failed_dependency = |jobs: &[MemoryJob], id: usize| {
+            dependencies
+                .iter()
+                .filter(|(job_id, _)| *job_id == id)
+                .any(|(_, depends_on)| FAILED_STATUSES.contains(&jobs[depends_on - 1].status))
+        };
+        // The given jobs are checked and all their dependants after them,
+        // then only the dependants of the jobs which changed.
+        let mut level = ids;
+        let mut changed = level.clone();
+        let mut blocked = 0;
+        while !level.is_empty() {
+            for id in level.drain(..) {
+                let failed = has_failed_dependency(&jobs, id);
+                let job = &mut jobs[id - 1];
+                match job.status {
+                    ExperimentStatus::NotRunning if failed => {
+                        job.status = ExperimentStatus::Blocked;
+                        blocked += 1;
+                    }
+                    ExperimentStatus::Blocked if !failed => {
+                        job.status = ExperimentStatus::NotRunning;
+                    }
+                    _ => continue,
+                }
+                changed.push(id);
+            }
+            level = dependencies
+                .iter()
+                .filter(|(_, depends_on)| changed.contains(depends_on))
+                .map(|&(job_id, _)| job_id)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            changed.clear();
+        }
+        Ok(blocked)
+    }
+
+    async fn get_waiting_on(&self) -> Result<Vec<(usize, usize)>> {
+        let jobs = self.jobs();
+        let mut waiting_on: Vec<(usize, usize)> = self
+            .dependencies()
+            .iter()
+            .copied()
+            .filter(|(_, depends_on)| {
+                jobs[depends_on - 1].status != ExperimentStatus::SuccessFinished
+            })
+            .collect();
+        waiting_on.sort_unstable();
+        Ok(waiting_on)
+    }
+
+    async fn claim_jobs(
+        &self,
+        mut capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>> {
+        let now = Instant::now();
+        let claim_token = Arc::new(new_claim_token());
+        let mut jobs = self.jobs();
+        let waiting: Vec<usize> = self
+            .dependencies()
+            .iter()
+            .filter(|(_, depends_on)| {
+                jobs[depends_on - 1].status != ExperimentStatus::SuccessFinished
+            })
+            .map(|(job_id, _)| *job_id)
+            .collect();
+        let mut available: Vec<&mut MemoryJob> = jobs
+            .iter_mut()
+            .filter(|j| {
+                j.status == ExperimentStatus::NotRunning
+                    && j.retry_after.is_none_or(|after| after <= now)
+                    && !waiting.contains(&j.id)
+                    && tags.matches(&j.tags)
+            })
+            .collect();
+        if shuffle {
+            available.shuffle(&mut thread_rng());
+        }
+        // The sort is stable, so jobs keep their id or shuffled order within a
+        // priority level.
+        available.sort_by_key(|j| Reverse(j.priority));
+        Ok(available
+            .into_iter()
+            .filter_map(|j| {
+                let job = Job {
+                    id: j.id,
+                    command: j.command.clone(),
+                    timeout: j.timeout,
+                    timeout_count: j.timeout_count,
+                    attempts: j.attempts,
+                    cpus: j.cpus,
+                    mem: j.mem,
+                    cwd: j.cwd.as_ref().map(PathBuf::from),
+                    env: j.env.clone(),
+                    claim_token: claim_token.clone(),
+                };
+                capacity.take(&job).then(|| {
+                    j.status = ExperimentStatus::Running;
+                    j.claim_token = Some(claim_token.clone());
+                    j.worker_id = Some(worker_id.to_owned());
+                    job
+                })
+            })
+            .collect())
+    }
+
+    async fn requeue_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let mut requeued = 0;
+        for j in self
+            .jobs()
+            .iter_mut()
+            .filter(|j| ids.contains(&j.id) && j.status != ExperimentStatus::Running)
+        {
+            j.status = ExperimentStatus::NotRunning;
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    async fn release_job(&self, id: usize, claim_token: &str) -> Result<bool> {
+        Ok(self.update_claimed_job(id, claim_token, |j| j.status = ExperimentStatus::NotRunning))
+    }
+
+    async fn record_attempt(
+        &self,
+        id: usize,
+        claim_token: &str,
+        new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
+    ) -> Result<bool> {
+        Ok(self.update_claimed_job(id, claim_token, |j| {
+            j.status = new_status;
+            j.attempts += 1;
+            j.timeout_count += usize::from(timed_out);
+            j.retry_after = retry_delay.map(|delay| Instant::now() + delay);
+        }))
+    }
+
+    async fn reset_jobs(
+        &self,
+        status: Option<ExperimentStatus>,
+        tags: &TagFilter,
+    ) -> Result<Vec<usize>> {
+        let mut ids = vec![];
+        for j in self
+            .jobs()
+            .iter_mut()
+            .filter(|j| status.is_none_or(|s| j.status == s) && tags.matches(&j.tags))
+        {
+            j.status = ExperimentStatus::NotRunning;
+            j.attempts = 0;
+            j.timeout_count = 0;
+            j.retry_after = None;
+            ids.push(j.id);
+        }
+        Ok(ids)
+    }
+
+    async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<usize> {
+        let mut changed = 0;
+        for j in self.jobs().iter_mut().filter(|j| match &jobs {
+            JobSelection::Ids(ids) => ids.contains(&j.id),
+            JobSelection::Matching(text) => j.command.contains(text.as_str()),
+        }) {
+            j.priority = priority;
+            changed += 1;
+        }
+        Ok(changed)
+    }
+
+    async fn count_fitting_jobs(&self, host: Capacity, tags: &TagFilter) -> Result<usize> {
+        let jobs = self.jobs();
+        let available = |j: &MemoryJob| j.status == ExperimentStatus::NotRunning;
+        let fits =
+            |j: &MemoryJob| j.cpus <= host.cpus && j.mem <= host.mem && tags.matches(&j.tags);
+        let mut stuck: HashSet<usize> = jobs
+            .iter()
+            .filter(|j| available(j) && !fits(j))
+            .map(|j| j.id)
+            .collect();
+        // Each pass reaches one more level of dependants.
+        loop {
+            let dependants: Vec<usize> = self
+                .dependencies()
+                .iter()
+                .filter(|(job_id, depends_on)| {
+                    stuck.contains(depends_on)
+                        && !stuck.contains(job_id)
+                        && available(&jobs[job_id - 1])
+                })
+                .map(|(job_id, _)| *job_id)
+                .collect();
+            if dependants.is_empty() {
+                break;
+            }
+            stuck.extend(dependants);
+        }
+        Ok(jobs
+            .iter()
+            .filter(|j| available(j) && fits(j) && !stuck.contains(&j.id))
+            .count())
+    }
+
+    async fn count_jobs_by_status(
+        &self,
+        tags: &TagFilter,
+    ) -> Result<Vec<(ExperimentStatus, usize)>> {
+        let mut counts: Vec<(ExperimentStatus, usize)> = vec![];
+        for j in self.jobs().iter().filter(|j| tags.matches(&j.tags)) {
+            match counts.iter_mut().find(|(s, _)| *s == j.status) {
+                Some((_, c)) => *c += 1,
+                None => counts.push((j.status, 1)),
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn record_run_stats(
+        &self,
+        id: usize,
+        claim_token: &str,
+        stats: RunStats,
+    ) -> Result<bool> {
+        Ok(self.update_claimed_job(id, claim_token, |j| j.run = stats))
+    }
+
+    async fn get_all_jobs(&self) -> Result<Vec<JobInfo>> {
+        Ok(self
+            .jobs()
+            .iter()
+            .map(|j| JobInfo {
+                id: j.id,
+                command: j.command.to_string(),
+                status: j.status,
+                attempts: j.attempts,
+                run: j.run.clone(),
+            })
+            .collect())
+    }
+
+    async fn get_param_values(&self, name: &str) -> Result<Vec<(usize, String)>> {
+        Ok(self
+            .jobs()
+            .iter()
+            .filter_map(|j| {
+                j.params
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, value)| (j.id, value.clone()))
+            })
+            .collect())
+    }
+
+    async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()> {
+        self.workers()
+            .insert(worker_id.to_owned(), Instant::now() + lease);
+        Ok(())
+    }
+
+    async fn reclaim_lost_jobs(&self, max_attempts: Option<usize>) -> Result<Vec<usize>> {
+        let now = Instant::now();
+        let mut workers = self.workers();
+        let mut reclaimed = vec![];
+        for j in self.jobs().iter_mut().filter(|j| {
+            j.status == ExperimentStatus::Running
+                && j.worker_id
+                    .as_ref()
+                    .and_then(|w| workers.get(w))
+                    .is_some_and(|&expiry| expiry < now)
+        }) {
+            j.status = if max_attempts.is_some_and(|max| j.attempts + 1 >= max) {
+                ExperimentStatus::FailedFinished
+            } else {
+                ExperimentStatus::NotRunning
+            };
+            j.lost_count += 1;
+            j.attempts += 1;
+            reclaimed.push(j.id);
+        }
+        workers.retain(|_, expiry| *expiry >= now);
+        Ok(reclaimed)
+    }
+
+    async fn remove_worker(&self, worker_id: &str) -> Result<()> {
+        self.workers().remove(worker_id);
+        Ok(())
+    }
+
+    async fn get_setting(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.settings().get(name).cloned())
+    }
+
+    async fn set_setting(&self, name: &str, value: Option<String>) -> Result<()> {
+        match value {
+            Some(value) => self.settings().insert(name.to_owned(), value),
+            None => self.settings().remove(name),
+        };
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        Ok(())
+    }
+}
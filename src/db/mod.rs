@@ -1,73 +1,552 @@
+mod config;
+mod jobfile;
+mod memory;
+mod mysql;
+mod postgres;
 mod show;
+mod sqlite;
 mod table;
 
+pub(crate) use config::DatabaseConfig;
+pub(crate) use jobfile::JobFormat;
+pub(crate) use table::{parse_tag, Requeue};
+
 use anyhow::Result;
-use mysql_async::Pool;
-use serde::Deserialize;
-use std::{path::Path, sync::Arc};
+use async_trait::async_trait;
+use config::DatabaseBackend;
+use memory::MemoryDatabase;
+use mysql::MySqlDatabase;
+use postgres::PostgresDatabase;
+use sha2::{Digest, Sha256};
+use sqlite::SqliteDatabase;
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum DatabaseError {
+    #[error("Config file is missing the `{0}` key required by the selected backend")]
+    MissingKey(&'static str),
+    #[error("No config file given with --config or DPR_CONFIG, and none found in $XDG_CONFIG_HOME/dpr/config.toml or ~/.dpr.toml")]
+    NoConfigFile,
+    #[error("Config file has no [client] section, add one or pick a profile with --profile")]
+    MissingClient,
+    #[error("Config file has no [profile.{0}] section")]
+    UnknownProfile(String),
+    #[error("Unknown job file format `{0}`, expected text, csv, jsonl or toml")]
+    UnknownFormat(String),
+    #[error("Unknown field `{0}` in job file, expected command, cwd, env, timeout, cpus, mem, priority, label, after or tags")]
+    UnknownField(String),
+    #[error("Invalid value for field `{0}` of a job record, expected a string, a number or a list of them, or a table of variables for `env`")]
+    InvalidField(String),
+    #[error("Record {0} of the job file has no command")]
+    MissingCommand(usize),
+    #[error("Invalid environment variable name `{0}`")]
+    InvalidEnvName(String),
+    #[error("Line {0} of the job file is not a valid record: {1}")]
+    InvalidRecord(usize, String),
+    #[error("Job `{0}` is already loaded, use --on-duplicate skip or requeue to load the others")]
+    DuplicateJob(String),
+    #[error("Unknown duplicate handling `{0}`, expected skip, error or requeue")]
+    UnknownOnDuplicate(String),
+    #[error("Invalid number of cores `{0}`, expected a positive number")]
+    InvalidCpus(String),
+    #[error("Invalid priority `{0}`, expected an integer")]
+    InvalidPriority(String),
+    #[error("Invalid label `{0}`, labels cannot be numbers or contain commas")]
+    InvalidLabel(String),
+    #[error("Invalid tag `{0}`, tags are 1 to 64 characters without spaces or commas")]
+    InvalidTag(String),
+    #[error("No job with the id or label `{0}` to depend on")]
+    UnknownDependency(String),
+    #[error("Jobs of the commands file depend on each other in a cycle")]
+    DependencyCycle,
+    #[error("Unknown status `{0}`, expected failed, timeout, killed or launch-error")]
+    UnknownRetryStatus(String),
+    #[error("Config file sets `pool_min` above `pool_max`")]
+    PoolSize,
+    #[error("Timed out connecting to the database")]
+    ConnectTimeout,
+    #[error("Timeout of {0} seconds is too large for the PostgreSQL backend")]
+    TimeoutTooLarge(u64),
+    #[error("Table schema version {0} is outdated, upgrade it with `dpr edit --migrate`")]
+    OutdatedSchema(usize),
+    #[error(
+        "Table schema version {0} is newer than the version {SCHEMA_VERSION} this dpr understands"
+    )]
+    UnknownSchema(usize),
+    #[error("Invalid schema version {0}, expected 1 to {SCHEMA_VERSION}")]
+    InvalidSchemaVersion(usize),
+}
+
+/// A runner ended a job which it no longer holds: its lease ran out and the
+/// job was reclaimed, and maybe claimed by another runner since.
+#[derive(Error, Debug)]
+#[error("Job {0} was reclaimed as its runner lost its lease, its result was not recorded")]
+pub(crate) struct LostLease(pub(crate) usize);
+
+/// Version of the experiment table schema created and used by this build. Each
+/// backend keeps one migration step per version after the first.
+pub(crate) const SCHEMA_VERSION: usize = 15;
+
+/// Tables created before versions were recorded in `dpr_schema_versions` are
+/// recognised by their columns: version 2 added `claim_token`.
+fn legacy_schema_version(has_claim_token: bool) -> usize {
+    if has_claim_token {
+        2
+    } else {
+        1
+    }
+}
+
+/// Storage operations of an experiment table. Every backend keeps the same
+/// schema and status codes, so the run loop does not depend on which one is
+/// configured.
+#[async_trait]
+pub(crate) trait JobStore: std::fmt::Debug + Send + Sync {
+    /// Creates the table with the schema of the given version, or empties it
+    /// if it already exists. The settings of the table are cleared as well.
+    /// Versions before `SCHEMA_VERSION` are only created to test migrations.
+    async fn create_table(&self, version: usize) -> Result<()>;
+    async fn schema_version(&self) -> Result<usize>;
+    /// Upgrades the table in place from the given schema version to
+    /// `SCHEMA_VERSION`, keeping its jobs.
+    async fn migrate(&self, from: usize) -> Result<()>;
+    /// Inserts the jobs with their tags and parameter values, without their
+    /// dependencies, and returns their ids in order.
+    async fn insert_jobs(&self, table_entries: Vec<TableEntry>) -> Result<Vec<usize>>;
+    /// Inserts available jobs made of a command only, which a table of any
+    /// schema version can hold.
+    async fn insert_commands(&self, commands: Vec<String>) -> Result<()>;
+    /// Content hash, id and status of every job which has a hash.
+    async fn get_content_hashes(&self) -> Result<Vec<(String, usize, ExperimentStatus)>>;
+    /// Ids of the jobs with the given id or label.
+    async fn find_jobs(&self, dependency: &Dependency) -> Result<Vec<usize>>;
+    /// Records that each job of a pair can only run once the second one
+    /// succeeded.
+    async fn insert_dependencies(&self, dependencies: Vec<(usize, usize)>) -> Result<()>;
+    /// Marks the given jobs and their dependants, even indirect ones, as
+    /// blocked if they are available and depend on a job which ended without
+    /// success or is blocked itself, and makes them available again if they
+    /// are blocked and no longer do. Returns how many jobs were blocked.
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<usize>;
+    /// Pairs of jobs and the dependencies they wait on, which did not succeed
+    /// yet, ordered by job.
+    async fn get_waiting_on(&self) -> Result<Vec<(usize, usize)>>;
+    /// Marks available jobs fitting in the capacity and passing the tag
+    /// filter, and whose dependencies all succeeded, as running by the given
+    /// worker and returns them, highest priority first. No other runner
+    /// sharing the table can claim the same jobs.
+    async fn claim_jobs(
+        &self,
+        capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>>;
+    /// Makes the given jobs available again, unless a runner holds them, and
+    /// returns how many there were.
+    async fn requeue_jobs(&self, ids: Vec<usize>) -> Result<usize>;
+    /// Makes a job available again without counting its attempt, if it is
+    /// still running under the given claim. Returns whether it was.
+    async fn release_job(&self, id: usize, claim_token: &str) -> Result<bool>;
+    /// Ends an attempt of a job running under the given claim: sets its
+    /// status and counts the attempt, and the timeout if it was killed for
+    /// running over its time limit. A job made available again with a
+    /// `retry_delay` cannot be claimed before the delay has passed. Returns
+    /// false, changing nothing, if the job is no longer running under that
+    /// claim.
+    async fn record_attempt(
+        &self,
+        id: usize,
+        claim_token: &str,
+        new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
+    ) -> Result<bool>;
+    /// Makes jobs with the given status available again, or every job if no
+    /// status is given, among those passing the tag filter, and returns their
+    /// ids. They can be claimed right away.
+    async fn reset_jobs(
+        &self,
+        status: Option<ExperimentStatus>,
+        tags: &TagFilter,
+    ) -> Result<Vec<usize>>;
+    /// Sets the priority of the selected jobs and returns how many there were.
+    async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<usize>;
+    /// Counts the available jobs passing the tag filter and small enough to
+    /// run on a host with the given capacity, including those waiting to be
+    /// retried. Jobs depending on an available job which the host cannot run,
+    /// even indirectly, are left out, as they would wait for it forever.
+    async fn count_fitting_jobs(&self, host: Capacity, tags: &TagFilter) -> Result<usize>;
+    async fn count_jobs_by_status(
+        &self,
+        tags: &TagFilter,
+    ) -> Result<Vec<(ExperimentStatus, usize)>>;
+    /// Records how the last run of a job running under the given claim ended
+    /// and the resources it used. Returns false, changing nothing, if the job
+    /// is no longer running under that claim.
+    async fn record_run_stats(&self, id: usize, claim_token: &str, stats: RunStats)
+        -> Result<bool>;
+    /// All jobs with their last run, ordered by id.
+    async fn get_all_jobs(&self) -> Result<Vec<JobInfo>>;
+    /// Ids of the jobs with a value for the given template parameter, and
+    /// their values, ordered by id.
+    async fn get_param_values(&self, name: &str) -> Result<Vec<(usize, String)>>;
+    /// Records that the worker is alive, in the clock of the database. The
+    /// worker is lost once `lease` has passed without another heartbeat.
+    async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()>;
+    /// Makes the running jobs of lost workers available again, counting them
+    /// as lost attempts, and forgets those workers. Jobs reaching
+    /// `max_attempts` fail instead. Returns the ids of the reclaimed jobs.
+    async fn reclaim_lost_jobs(&self, max_attempts: Option<usize>) -> Result<Vec<usize>>;
+    /// Forgets a worker which stopped cleanly.
+    async fn remove_worker(&self, worker_id: &str) -> Result<()>;
+    /// Reads a setting of the table, `None` if it was never set.
+    async fn get_setting(&self, name: &str) -> Result<Option<String>>;
+    /// Stores a setting of the table, or removes it if `value` is `None`.
+    async fn set_setting(&self, name: &str, value: Option<String>) -> Result<()>;
+    async fn disconnect(&self) -> Result<()>;
+}
+
+/// Columns read into a `JobInfo`, in order.
+const JOB_INFO_COLUMNS: &str = "id, command, status, attempts, exit_code, term_signal, \
+    start_time, end_time, wall_time, user_time, sys_time, max_rss, launch_error";
 
-#[derive(Deserialize)]
-pub(crate) struct DatabaseConfig {
-    client: DatabaseClient,
+/// Random token tagging the rows taken by one claim. A runner only records the
+/// end of a job while the job still carries the token of its claim, so a job
+/// reclaimed after its runner lost its lease is not overwritten.
+fn new_claim_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
 }
 
-#[derive(Deserialize)]
-struct DatabaseClient {
-    host: String,
-    user: String,
-    password: String,
+pub(crate) struct TableEntry {
+    pub(crate) command: String,
+    pub(crate) status: ExperimentStatus,
+    pub(crate) timeout: Option<Duration>,
+    /// Cores needed by the job, 1 if it did not declare any.
+    pub(crate) cpus: usize,
+    /// Memory needed by the job, in MiB, 0 if it did not declare any.
+    pub(crate) mem: u64,
+    /// Jobs with a higher priority are claimed first.
+    pub(crate) priority: i32,
+    /// Name of the job, which other jobs can depend on. Several jobs can
+    /// share a label.
+    pub(crate) label: Option<String>,
+    /// Jobs which must succeed before this one can run.
+    pub(crate) after: Vec<Dependency>,
+    pub(crate) tags: Vec<String>,
+    /// Names and values of the template parameters the job was expanded
+    /// from.
+    pub(crate) params: Vec<(String, String)>,
+    pub(crate) cwd: Option<String>,
+    pub(crate) env: Vec<(String, String)>,
+    /// Jobs running the same as this one which come before it in the same
+    /// load, so repeated commands are not taken for duplicates.
+    pub(crate) copy: usize,
+}
+
+/// A job, or all the jobs sharing a label, which another job depends on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Dependency {
+    Id(usize),
+    Label(String),
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dependency::Id(id) => write!(f, "{}", id),
+            Dependency::Label(label) => write!(f, "{}", label),
+        }
+    }
 }
 
-impl DatabaseConfig {
-    pub(crate) async fn from_config_file(file_name: &Path) -> Result<DatabaseConfig> {
-        let file_contents = String::from_utf8(tokio::fs::read(file_name).await?)?;
-        let dbc: DatabaseConfig = toml::from_str(&file_contents)?;
-        Ok(dbc)
+impl TableEntry {
+    /// Hash of what the job runs, its command, working directory and
+    /// environment, and of its copy number. Jobs with the same hash are
+    /// duplicates of each other.
+    pub(crate) fn content_hash(&self) -> String {
+        content_hash(&self.command, self.cwd.as_deref(), &self.env, self.copy)
     }
+}
 
-    pub(crate) fn get_user(&self) -> &str {
-        &self.client.user
+fn content_hash(command: &str, cwd: Option<&str>, env: &[(String, String)], copy: usize) -> String {
+    let mut env: Vec<&(String, String)> = env.iter().collect();
+    env.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(command.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(cwd.unwrap_or_default().as_bytes());
+    for (name, value) in env {
+        hasher.update(b"\0");
+        hasher.update(name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_bytes());
+    }
+    if copy > 0 {
+        hasher.update(format!("\0#{}", copy).as_bytes());
     }
+    format!("{:x}", hasher.finalize())
+}
 
-    pub(crate) fn get_password(&self) -> &str {
-        &self.client.password
+/// Statement of a migration step: SQL, with `{table}` standing for the table
+/// name, or a change the backend makes itself.
+#[derive(Clone, Copy, Debug)]
+enum MigrationStep {
+    Sql(&'static str),
+    /// Fills the content hashes of the jobs loaded before the table had them,
+    /// so loading them again finds them.
+    FillContentHashes,
+    /// Drops the check constraint bounding the status, before a wider one is
+    /// added. SQLite cannot drop a constraint and rebuilds the table instead.
+    DropStatusCheck,
+}
+
+/// Content hashes of the jobs given by id, command and working directory, in
+/// id order, and of their environment variables given by job id. Copies of a
+/// job are numbered in id order, as if the jobs had all been loaded at once.
+fn fill_content_hashes(
+    jobs: Vec<(usize, String, Option<String>)>,
+    env: Vec<(usize, String, String)>,
+) -> Vec<(String, usize)> {
+    let mut env_of_job: HashMap<usize, Vec<(String, String)>> = HashMap::new();
+    for (job_id, name, value) in env {
+        env_of_job.entry(job_id).or_default().push((name, value));
     }
+    let mut copies: HashMap<String, usize> = HashMap::new();
+    jobs.into_iter()
+        .map(|(id, command, cwd)| {
+            let env = env_of_job.remove(&id).unwrap_or_default();
+            let copy = copies
+                .entry(content_hash(&command, cwd.as_deref(), &env, 0))
+                .or_default();
+            let hash = content_hash(&command, cwd.as_deref(), &env, *copy);
+            *copy += 1;
+            (hash, id)
+        })
+        .collect()
+}
+
+/// What loading does with jobs already in the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OnDuplicate {
+    /// Load none of the jobs.
+    Error,
+    /// Load the other jobs.
+    Skip,
+    /// Load the other jobs and make the duplicates which ended available
+    /// again.
+    Requeue,
+}
+
+impl FromStr for OnDuplicate {
+    type Err = anyhow::Error;
 
-    pub(crate) fn get_host(&self) -> &str {
-        &self.client.host
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(OnDuplicate::Error),
+            "skip" => Ok(OnDuplicate::Skip),
+            "requeue" => Ok(OnDuplicate::Requeue),
+            _ => Err(DatabaseError::UnknownOnDuplicate(s.to_owned()).into()),
+        }
+    }
+}
+
+/// Jobs an `edit` operation applies to.
+#[derive(Clone, Debug)]
+pub(crate) enum JobSelection {
+    Ids(Vec<usize>),
+    /// Jobs whose command contains the given text.
+    Matching(String),
+}
+
+/// Selects the jobs carrying all the `include` tags and none of the `exclude`
+/// ones. The default filter selects every job.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TagFilter {
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Whether a job with the given tags passes the filter.
+    fn matches(&self, tags: &[String]) -> bool {
+        self.include.iter().all(|tag| tags.contains(tag))
+            && !self.exclude.iter().any(|tag| tags.contains(tag))
+    }
+
+    /// SQL conditions, each starting with ` AND `, selecting the jobs of
+    /// `table` which pass the filter. `placeholder` gives the placeholder of
+    /// the `i`-th tag of `values`.
+    fn sql(&self, table: &str, placeholder: impl Fn(usize) -> String) -> String {
+        let has_tag = |i| {
+            format!(
+                "EXISTS (SELECT 1 FROM {0}_tags g WHERE g.job_id = {0}.id AND g.tag = {1})",
+                table,
+                placeholder(i)
+            )
+        };
+        let include = (0..self.include.len()).map(|i| format!(" AND {}", has_tag(i)));
+        let exclude = (self.include.len()..self.include.len() + self.exclude.len())
+            .map(|i| format!(" AND NOT {}", has_tag(i)));
+        include.chain(exclude).collect()
+    }
+
+    /// Tags bound to the placeholders of `sql`, in order.
+    fn values(&self) -> impl Iterator<Item = &String> {
+        self.include.iter().chain(&self.exclude)
+    }
+}
+
+/// Room left on a host for the jobs a runner claims.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Capacity {
+    /// Jobs the runner can still start.
+    pub(crate) jobs: usize,
+    /// Cores not taken by the running jobs.
+    pub(crate) cpus: usize,
+    /// Memory not taken by the running jobs, in MiB.
+    pub(crate) mem: u64,
+}
+
+impl Capacity {
+    /// Takes the cores and memory of the job if they are left. Jobs are
+    /// offered in claim order, and those which do not fit are skipped so
+    /// smaller jobs after them can still use the remaining room.
+    fn take(&mut self, job: &Job) -> bool {
+        if self.jobs == 0 || job.cpus > self.cpus || job.mem > self.mem {
+            return false;
+        }
+        self.jobs -= 1;
+        self.cpus -= job.cpus;
+        self.mem -= job.mem;
+        true
+    }
+}
+
+/// How the last run of a job ended and the resources it used, all unset for
+/// jobs which never ran.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RunStats {
+    pub(crate) exit_code: Option<i32>,
+    /// Signal which terminated the job.
+    pub(crate) signal: Option<i32>,
+    /// Unix timestamps, in seconds.
+    pub(crate) start_time: Option<i64>,
+    pub(crate) end_time: Option<i64>,
+    /// Elapsed, user CPU and system CPU times, in seconds.
+    pub(crate) wall_time: Option<f64>,
+    pub(crate) user_time: Option<f64>,
+    pub(crate) sys_time: Option<f64>,
+    /// Peak resident set size, in KiB.
+    pub(crate) max_rss: Option<i64>,
+    /// Why the job could not be started.
+    pub(crate) launch_error: Option<String>,
+}
+
+/// A job as listed by `show`.
+#[derive(Debug)]
+pub(crate) struct JobInfo {
+    pub(crate) id: usize,
+    pub(crate) command: String,
+    pub(crate) status: ExperimentStatus,
+    pub(crate) attempts: usize,
+    pub(crate) run: RunStats,
+}
+
+/// When jobs of a table are run again after they end without success.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RetryPolicy {
+    /// Runs a job gets at most, including the first one. Jobs are not retried
+    /// if it is not set.
+    pub(crate) max_attempts: Option<usize>,
+    /// Statuses of the runs which are retried.
+    pub(crate) retry_on: Vec<ExperimentStatus>,
+    /// Time a job waits before it can be claimed again.
+    pub(crate) backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Whether a job ending its attempt with the given status is run again.
+    fn retries(&self, job: &Job, status: ExperimentStatus) -> bool {
+        self.max_attempts
+            .is_some_and(|max| job.attempts + 1 < max && self.retry_on.contains(&status))
     }
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct ExperimentDatabase {
-    pub(crate) pool: Pool,
-    table_name: Arc<String>,
+    store: Arc<dyn JobStore>,
 }
 
 impl ExperimentDatabase {
     pub fn from_db_config(db_config: DatabaseConfig, table_name: String) -> Result<Self> {
-        let url = format!(
-            "mysql://{}:{}@{}/{}_dpemr_experiments",
-            db_config.get_user(),
-            db_config.get_password(),
-            db_config.get_host(),
-            db_config.get_user()
-        );
-        let pool = Pool::from_url(url)?;
-        Ok(Self {
-            pool,
-            table_name: Arc::new(table_name),
-        })
+        let table_name = Arc::new(table_name);
+        let store: Arc<dyn JobStore> = match db_config.get_backend() {
+            DatabaseBackend::MySql => Arc::new(MySqlDatabase::new(&db_config, table_name)?),
+            DatabaseBackend::Postgres => Arc::new(PostgresDatabase::new(&db_config, table_name)?),
+            DatabaseBackend::Sqlite => {
+                Arc::new(SqliteDatabase::open(db_config.get_path()?, table_name)?)
+            }
+            DatabaseBackend::Memory => Arc::new(MemoryDatabase::default()),
+        };
+        Ok(Self::new(store))
+    }
+
+    pub fn new(store: Arc<dyn JobStore>) -> Self {
+        Self { store }
+    }
+
+    pub async fn disconnect(self) -> Result<()> {
+        self.store.disconnect().await
     }
 }
 
-#[derive(Copy, Clone)]
+#[cfg(test)]
+impl ExperimentDatabase {
+    /// A table living in the memory of the test using it.
+    pub(crate) fn in_memory() -> Self {
+        Self::new(Arc::new(MemoryDatabase::default()))
+    }
+
+    /// All jobs with their last run, ordered by id.
+    pub(crate) async fn get_all_jobs(&self) -> Result<Vec<JobInfo>> {
+        self.store.get_all_jobs().await
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ExperimentStatus {
     NotRunning = 0,
     Running = 1,
     SuccessFinished = 2,
     FailedFinished = 3,
     TimedOut = 4,
+    /// Terminated by a signal, e.g. crashed or killed by the OOM killer.
+    Killed = 5,
+    /// Could not be started, e.g. because the program does not exist.
+    LaunchError = 6,
+    /// Cannot run as a job it depends on ended without success.
+    Blocked = 7,
+}
+
+/// Statuses of jobs which ended without success, and of the jobs blocked by
+/// them, so the jobs depending on them cannot run.
+const FAILED_STATUSES: [ExperimentStatus; 5] = [
+    ExperimentStatus::FailedFinished,
+    ExperimentStatus::TimedOut,
+    ExperimentStatus::Killed,
+    ExperimentStatus::LaunchError,
+    ExperimentStatus::Blocked,
+];
+
+/// `FAILED_STATUSES` as a list of codes for SQL `IN` clauses.
+fn failed_status_codes() -> String {
+    FAILED_STATUSES
+        .iter()
+        .map(|status| status.to_db_code().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl ExperimentStatus {
@@ -78,7 +557,10 @@ impl ExperimentStatus {
             2 => ExperimentStatus::SuccessFinished,
             3 => ExperimentStatus::FailedFinished,
             4 => ExperimentStatus::TimedOut,
-            // SAFETY: If experiment status only constructed for status codes from db table, it's guaranteed to be bounded. 
+            5 => ExperimentStatus::Killed,
+            6 => ExperimentStatus::LaunchError,
+            7 => ExperimentStatus::Blocked,
+            // SAFETY: If experiment status only constructed for status codes from db table, it's guaranteed to be bounded.
             _ => unreachable!(),
         }
     }
@@ -96,6 +578,9 @@ impl std::fmt::Display for ExperimentStatus {
             ExperimentStatus::SuccessFinished => write!(f, "Success"),
             ExperimentStatus::FailedFinished => write!(f, "Failure"),
             ExperimentStatus::TimedOut => write!(f, "Timeout"),
+            ExperimentStatus::Killed => write!(f, "Killed"),
+            ExperimentStatus::LaunchError => write!(f, "Launch error"),
+            ExperimentStatus::Blocked => write!(f, "Blocked"),
         }
     }
 }
@@ -104,4 +589,20 @@ impl std::fmt::Display for ExperimentStatus {
 pub struct Job {
     pub id: usize,
     pub command: Arc<String>,
+    /// Time limit of this job, overriding the one given to `run --timeout`.
+    pub timeout: Option<Duration>,
+    /// How many times the job was killed for running over its time limit,
+    /// which is its tier in the timeout ladder of the table.
+    pub timeout_count: usize,
+    /// Runs of the job which ended or were lost before this one.
+    pub attempts: usize,
+    /// Cores and memory, in MiB, the job declared it needs.
+    pub cpus: usize,
+    pub mem: u64,
+    /// Directory the command runs in, the one of the runner if `None`.
+    pub cwd: Option<PathBuf>,
+    /// Variables added to the environment of the command.
+    pub env: Vec<(String, String)>,
+    /// Token of the claim which gave the job to this runner.
+    pub claim_token: Arc<String>,
 }
@@ -0,0 +1,1164 @@
+use super::{
+    config::SslMode,
+    failed_status_codes, fill_content_hashes, legacy_schema_version, new_claim_token, Capacity,
+    DatabaseConfig, DatabaseError, Dependency, ExperimentStatus, Job, JobInfo, JobSelection,
+    JobStore,
+    MigrationStep::{self, DropStatusCheck, FillContentHashes, Sql},
+    RunStats, TableEntry, TagFilter, JOB_INFO_COLUMNS, SCHEMA_VERSION,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use mysql_async::{
+    prelude::*, Conn, OptsBuilder, Params, Pool, PoolConstraints, PoolOpts, Row, SslOpts, TxOpts,
+    Value, DEFAULT_POOL_CONSTRAINTS,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::timeout;
+
+const SCHEMA_VERSIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_schema_versions (
+    table_name VARCHAR(64) NOT NULL,
+    version int NOT NULL,
+    PRIMARY KEY (table_name))";
+
+const SETTINGS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_table_settings (
+    table_name VARCHAR(64) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    value VARCHAR(500) NOT NULL,
+    PRIMARY KEY (table_name, name))";
+
+const WORKERS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_workers (
+    worker_id VARCHAR(64) NOT NULL,
+    table_name VARCHAR(64) NOT NULL,
+    heartbeat BIGINT NOT NULL,
+    lease BIGINT NOT NULL,
+    PRIMARY KEY (worker_id))";
+
+/// MySQL/MariaDB storage for the experiment tables.
+#[derive(Clone, Debug)]
+pub(crate) struct MySqlDatabase {
+    pool: Pool,
+    connect_timeout: Option<Duration>,
+    table_name: Arc<String>,
+}
+
+/// Statements upgrading a table from schema version `i + 1` to `i + 2`. DDL
+/// commits implicitly in MySQL, so the version is recorded after each step,
+/// and a step interrupted halfway is run again from its first statement.
+const MIGRATIONS: [&[MigrationStep]; SCHEMA_VERSION - 1] = [
+    // 2: row-level claims
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN claim_token CHAR(32)"),
+        Sql("CREATE INDEX {table}_status ON {table} (status)"),
+        Sql("CREATE INDEX {table}_claim_token ON {table} (claim_token)"),
+    ],
+    // 3: per-job timeouts, in seconds
+    &[Sql("ALTER TABLE {table} ADD COLUMN timeout int")],
+    // 4: timeout ladder tiers
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN timeout_count int NOT NULL DEFAULT 0",
+    )],
+    // 5: exit status, times and resource usage of the last run
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN exit_code int"),
+        Sql("ALTER TABLE {table} ADD COLUMN term_signal int"),
+        Sql("ALTER TABLE {table} ADD COLUMN start_time BIGINT"),
+        Sql("ALTER TABLE {table} ADD COLUMN end_time BIGINT"),
+        Sql("ALTER TABLE {table} ADD COLUMN wall_time DOUBLE"),
+        Sql("ALTER TABLE {table} ADD COLUMN user_time DOUBLE"),
+        Sql("ALTER TABLE {table} ADD COLUMN sys_time DOUBLE"),
+        Sql("ALTER TABLE {table} ADD COLUMN max_rss BIGINT"),
+    ],
+    // 6: killed and launch error statuses
+    &[
+        DropStatusCheck,
+        Sql("ALTER TABLE {table} ADD CONSTRAINT {table}_status_check CHECK(status<7)"),
+        Sql("ALTER TABLE {table} ADD COLUMN launch_error TEXT"),
+    ],
+    // 7: workers holding the jobs, and jobs they lost
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN worker_id VARCHAR(64)"),
+        Sql("ALTER TABLE {table} ADD COLUMN lost_count INT NOT NULL DEFAULT 0"),
+    ],
+    // 8: attempts, and when a retried job can be claimed again
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN attempts INT NOT NULL DEFAULT 0"),
+        Sql("ALTER TABLE {table} ADD COLUMN retry_after BIGINT"),
+    ],
+    // 9: cores and memory, in MiB, needed by each job
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cpus INT NOT NULL DEFAULT 1"),
+        Sql("ALTER TABLE {table} ADD COLUMN mem BIGINT NOT NULL DEFAULT 0"),
+    ],
+    // 10: priorities, the highest claimed first
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN priority INT NOT NULL DEFAULT 0",
+    )],
+    // 11: labels, dependencies and the blocked status
+    &[
+        DropStatusCheck,
+        Sql("ALTER TABLE {table} ADD CONSTRAINT {table}_status_check CHECK(status<8)"),
+        Sql("ALTER TABLE {table} ADD COLUMN label VARCHAR(255)"),
+        Sql("CREATE INDEX {table}_label ON {table} (label)"),
+        Sql(r"CREATE TABLE {table}_deps (
+            job_id INT NOT NULL,
+            depends_on INT NOT NULL,
+            PRIMARY KEY (job_id, depends_on))"),
+        Sql("CREATE INDEX {table}_deps_depends_on ON {table}_deps (depends_on)"),
+    ],
+    // 12: tags
+    &[
+        Sql(r"CREATE TABLE {table}_tags (
+            job_id INT NOT NULL,
+            tag VARCHAR(64) NOT NULL,
+            PRIMARY KEY (job_id, tag))"),
+        Sql("CREATE INDEX {table}_tags_tag ON {table}_tags (tag)"),
+    ],
+    // 13: values of the template parameters of each job
+    &[Sql(r"CREATE TABLE {table}_params (
+        job_id INT NOT NULL,
+        name VARCHAR(64) NOT NULL,
+        value VARCHAR(500) NOT NULL,
+        PRIMARY KEY (job_id, name))")],
+    // 14: working directories and environment variables
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cwd VARCHAR(500)"),
+        Sql(r"CREATE TABLE {table}_env (
+            job_id INT NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (job_id, name))"),
+    ],
+    // 15: content hashes, unique so a job cannot be loaded twice
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN content_hash CHAR(64)"),
+        FillContentHashes,
+        Sql("CREATE UNIQUE INDEX {table}_content_hash ON {table} (content_hash)"),
+    ],
+];
+
+/// Server errors of statements whose change is already in the table, met when
+/// a migration step is run again: existing table, column, index or check
+/// constraint, the last one under its MariaDB and MySQL codes.
+const ALREADY_APPLIED: [u16; 5] = [1050, 1060, 1061, 1826, 3822];
+
+/// Placeholder of the `i`-th tag of a filter, bound by `with_tags`.
+fn tag_placeholder(i: usize) -> String {
+    format!(":tag{}", i)
+}
+
+/// Binds the tags of a filter next to the other parameters of a statement.
+fn with_tags(params: Params, tags: &TagFilter) -> Params {
+    let mut named = match params {
+        Params::Named(named) => named,
+        _ => HashMap::new(),
+    };
+    for (i, tag) in tags.values().enumerate() {
+        named.insert(format!("tag{}", i).into_bytes(), Value::from(tag));
+    }
+    if named.is_empty() {
+        Params::Empty
+    } else {
+        Params::Named(named)
+    }
+}
+
+impl MySqlDatabase {
+    pub(crate) fn new(db_config: &DatabaseConfig, table_name: Arc<String>) -> Result<Self> {
+        // Updates report the rows they matched, like the other backends, and
+        // not only those whose values changed.
+        let mut opts = OptsBuilder::default()
+            .client_found_rows(true)
+            .user(Some(db_config.get_user()?))
+            .pass(Some(db_config.get_password()?))
+            .db_name(Some(db_config.get_database()?));
+        opts = match db_config.get_socket() {
+            Some(socket) => opts.socket(Some(socket.to_string_lossy())),
+            None => opts.ip_or_hostname(db_config.get_host()?),
+        };
+        if let Some(port) = db_config.get_port() {
+            opts = opts.tcp_port(port);
+        }
+        let ssl_mode = db_config.get_ssl_mode();
+        if ssl_mode != SslMode::Disabled {
+            let ssl_opts = SslOpts::default()
+                .with_root_cert_path(db_config.get_ssl_ca().map(Path::to_path_buf))
+                .with_danger_accept_invalid_certs(ssl_mode == SslMode::Required)
+                .with_danger_skip_domain_validation(ssl_mode != SslMode::VerifyIdentity);
+            opts = opts.ssl_opts(ssl_opts);
+        }
+        let constraints = PoolConstraints::new(
+            db_config
+                .get_pool_min()
+                .unwrap_or(DEFAULT_POOL_CONSTRAINTS.min()),
+            db_config
+                .get_pool_max()
+                .unwrap_or(DEFAULT_POOL_CONSTRAINTS.max()),
+        )
+        .ok_or(DatabaseError::PoolSize)?;
+        opts = opts.pool_opts(PoolOpts::default().with_constraints(constraints));
+        Ok(Self {
+            pool: Pool::new(opts),
+            connect_timeout: db_config.get_connect_timeout(),
+            table_name,
+        })
+    }
+
+    /// mysql_async has no connect timeout of its own, so waiting for a pooled
+    /// connection is bounded here instead.
+    async fn get_conn(&self) -> Result<Conn> {
+        match self.connect_timeout {
+            Some(connect_timeout) => timeout(connect_timeout, self.pool.get_conn())
+                .await
+                .map_err(|_| DatabaseError::ConnectTimeout)?
+                .map_err(Into::into),
+            None => Ok(self.pool.get_conn().await?),
+        }
+    }
+
+    async fn set_schema_version(&self, conn: &mut Conn, version: usize) -> Result<()> {
+        conn.query_drop(SCHEMA_VERSIONS_TABLE).await?;
+        conn.exec_drop(
+            r"INSERT INTO dpr_schema_versions (table_name, version)
+            VALUES (:table_name, :version)
+            ON DUPLICATE KEY UPDATE version = VALUES(version)",
+            params! {
+                "table_name" => self.table_name.as_str(),
+                "version" => version,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Applies the migration steps from version `from` up to `to`.
+    async fn migrate_from(&self, conn: &mut Conn, from: usize, to: usize) -> Result<()> {
+        for (i, step) in MIGRATIONS.iter().enumerate().take(to - 1).skip(from - 1) {
+            for statement in step.iter() {
+                match statement {
+                    DropStatusCheck => self.drop_check_constraints(conn).await?,
+                    Sql(sql) => match conn
+                        .query_drop(sql.replace("{table}", &self.table_name))
+                        .await
+                    {
+                        Err(mysql_async::Error::Server(e)) if ALREADY_APPLIED.contains(&e.code) => {
+                        }
+                        res => res?,
+                    },
+                    FillContentHashes => self.fill_content_hashes(conn).await?,
+                }
+            }
+            self.set_schema_version(conn, i + 2).await?;
+        }
+        Ok(())
+    }
+
+    async fn fill_content_hashes(&self, conn: &mut Conn) -> Result<()> {
+        let jobs = conn
+            .query(format!(
+                "SELECT id, command, cwd FROM {} ORDER BY id",
+                self.table_name
+            ))
+            .await?;
+        let env = conn
+            .query(format!(
+                "SELECT job_id, name, value FROM {}_env",
+                self.table_name
+            ))
+            .await?;
+        conn.exec_batch(
+            format!(
+                "UPDATE {} SET content_hash = :content_hash WHERE id = :id",
+                self.table_name
+            ),
+            fill_content_hashes(jobs, env)
+                .into_iter()
+                .map(|(content_hash, id)| {
+                    params! {
+                        "content_hash" => content_hash,
+                        "id" => id,
+                    }
+                }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Drops the check constraints of the table, which only bound its status.
+    /// The first one was not named when the table was created, so the names
+    /// the server gave them are looked up.
+    async fn drop_check_constraints(&self, conn: &mut Conn) -> Result<()> {
+        let names: Vec<String> = conn
+            .exec(
+                r"SELECT CONSTRAINT_NAME FROM information_schema.TABLE_CONSTRAINTS
+                WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = :table_name
+                AND CONSTRAINT_TYPE = 'CHECK'",
+                params! {
+                    "table_name" => self.table_name.as_str(),
+                },
+            )
+            .await?;
+        for name in names {
+            conn.query_drop(format!(
+                "ALTER TABLE {} DROP CONSTRAINT `{}`",
+                self.table_name, name
+            ))
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobStore for MySqlDatabase {
+    async fn create_table(&self, version: usize) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(format!(
+            "DROP TABLE IF EXISTS {0}_deps, {0}_tags, {0}_params, {0}_env",
+            self.table_name
+        ))
+        .await?;
+        conn.query_drop(format!(
+            r"CREATE OR REPLACE TABLE {} (
+                    id int NOT NULL AUTO_INCREMENT,
+                    command VARCHAR(500) NOT NULL,
+                    status int NOT NULL,
+                    CHECK(status<5),
+                    PRIMARY KEY (id))",
+            self.table_name
+        ))
+        .await?;
+        self.set_schema_version(&mut conn, 1).await?;
+        conn.query_drop(SETTINGS_TABLE).await?;
+        conn.exec_drop(
+            "DELETE FROM dpr_table_settings WHERE table_name = :table_name",
+            params! {
+                "table_name" => self.table_name.as_str(),
+            },
+        )
+        .await?;
+        self.migrate_from(&mut conn, 1, version).await?;
+        Ok(())
+    }
+
+    async fn schema_version(&self) -> Result<usize> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(format!("SELECT id FROM {} LIMIT 0", self.table_name))
+            .await?;
+        conn.query_drop(SCHEMA_VERSIONS_TABLE).await?;
+        let version: Option<usize> = conn
+            .exec_first(
+                "SELECT version FROM dpr_schema_versions WHERE table_name = :table_name",
+                params! {
+                    "table_name" => self.table_name.as_str(),
+                },
+            )
+            .await?;
+        match version {
+            Some(version) => Ok(version),
+            None => {
+                let has_claim_token = conn
+                    .query_drop(format!(
+                        "SELECT claim_token FROM {} LIMIT 0",
+                        self.table_name
+                    ))
+                    .await
+                    .is_ok();
+                Ok(legacy_schema_version(has_claim_token))
+            }
+        }
+    }
+
+    async fn migrate(&self, from: usize) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        self.migrate_from(&mut conn, from, SCHEMA_VERSION).await
+    }
+
+    async fn insert_commands(&self, commands: Vec<String>) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        conn.exec_batch(
+            format!(
+                "INSERT INTO {} (command, status) VALUES (:command, :status)",
+                self.table_name
+            ),
+            commands.into_iter().map(|command| {
+                params! {
+                    "command" => command,
+                    "status" => ExperimentStatus::NotRunning.to_db_code(),
+                }
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_jobs(&self, table_entries: Vec<TableEntry>) -> Result<Vec<usize>> {
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let mut ids = Vec::with_capacity(table_entries.len());
+        for t in table_entries {
+            tx.exec_drop(
+                format!(
+                    r"INSERT INTO {}
+                                (command,status,timeout,cpus,mem,priority,label,cwd,content_hash)
+                                values (:command, :status, :timeout, :cpus, :mem, :priority, :label, :cwd,
+                                :content_hash)",
+                    self.table_name
+                ),
+                params! {
+                    "content_hash" => t.content_hash(),
+                    "command" => t.command,
+                    "status" => t.status.to_db_code(),
+                    "timeout" => t.timeout.map(|d| d.as_secs()),
+                    "cpus" => t.cpus,
+                    "mem" => t.mem,
+                    "priority" => t.priority,
+                    "label" => t.label,
+                    "cwd" => t.cwd,
+                },
+            )
+            .await?;
+            let id = tx.last_insert_id().unwrap_or_default();
+            tx.exec_batch(
+                format!(
+                    "INSERT INTO {}_tags (job_id, tag) VALUES (:job_id, :tag)",
+                    self.table_name
+                ),
+                t.tags.iter().map(|tag| {
+                    params! {
+                        "job_id" => id,
+                        "tag" => tag,
+                    }
+                }),
+            )
+            .await?;
+            tx.exec_batch(
+                format!(
+                    "INSERT INTO {}_params (job_id, name, value) VALUES (:job_id, :name, :value)",
+                    self.table_name
+                ),
+                t.params.iter().map(|(name, value)| {
+                    params! {
+                        "job_id" => id,
+                        "name" => name,
+                        "value" => value,
+                    }
+                }),
+            )
+            .await?;
+            tx.exec_batch(
+                format!(
+                    "INSERT INTO {}_env (job_id, name, value) VALUES (:job_id, :name, :value)",
+                    self.table_name
+                ),
+                t.env.iter().map(|(name, value)| {
+                    params! {
+                        "job_id" => id,
+                        "name" => name,
+                        "value" => value,
+                    }
+                }),
+            )
+            .await?;
+            ids.push(id as usize);
+        }
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    async fn get_content_hashes(&self) -> Result<Vec<(String, usize, ExperimentStatus)>> {
+        let mut conn = self.get_conn().await?;
+        let hashes = conn
+            .query_map(
+                format!(
+                    "SELECT content_hash, id, status FROM {} WHERE content_hash IS NOT NULL",
+                    self.table_name
+                ),
+                |(content_hash, id, status)| (content_hash, id, ExperimentStatus::new(status)),
+            )
+            .await?;
+        Ok(hashes)
+    }
+
+    async fn find_jobs(&self, dependency: &Dependency) -> Result<Vec<usize>> {
+        let mut conn = self.get_conn().await?;
+        let ids = match dependency {
+            Dependency::Id(id) => {
+                conn.exec(
+                    format!("SELECT id FROM {} WHERE id = :id", self.table_name),
+                    params! {
+                        "id" => id,
+                    },
+                )
+                .await?
+            }
+            Dependency::Label(label) => {
+                conn.exec(
+                    format!(
+                        "SELECT id FROM {} WHERE label = :label ORDER BY id",
+                        self.table_name
+                    ),
+                    params! {
+                        "label" => label,
+                    },
+                )
+                .await?
+            }
+        };
+        Ok(ids)
+    }
+
+    async fn insert_dependencies(&self, dependencies: Vec<(usize, usize)>) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        let params = dependencies.into_iter().map(|(job_id, depends_on)| {
+            params! {
+                "job_id" => job_id,
+                "depends_on" => depends_on,
+            }
+        });
+        conn.exec_batch(
+            format!(
+                "INSERT INTO {}_deps (job_id, depends_on) VALUES (:job_id, :depends_on)",
+                self.table_name
+            ),
+            params,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// MySQL cannot update a table read in a subquery of the same statement,
+    /// so whether a job has a failed dependency is read before updating it.
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let failed_dependency = format!(
+            r"SELECT EXISTS (SELECT 1 FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+            WHERE d.job_id = :id AND p.status IN ({1}))",
+            self.table_name,
+            failed_status_codes()
+        );
+        let set_status = format!(
+            "UPDATE {} SET status = :new_status WHERE id = :id AND status = :status",
+            self.table_name
+        );
+        let dependants = format!(
+            "SELECT job_id FROM {}_deps WHERE depends_on = :id",
+            self.table_name
+        );
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        // The given jobs are checked and all their dependants after them,
+        // then only the dependants of the jobs which changed.
+        let mut level = ids;
+        let mut changed = level.clone();
+        let mut blocked = 0;
+        while !level.is_empty() {
+            for id in level.drain(..) {
+                let failed: bool = tx
+                    .exec_first(&failed_dependency, params! { "id" => id })
+                    .await?
+                    .unwrap_or_default();
+                let (status, new_status) = if failed {
+                    (ExperimentStatus::NotRunning, ExperimentStatus::Blocked)
+                } else {
+                    (ExperimentStatus::Blocked, ExperimentStatus::NotRunning)
+                };
+                tx.exec_drop(
+                    &set_status,
+                    params! {
+                        "new_status" => new_status.to_db_code(),
+                        "id" => id,
+                        "status" => status.to_db_code(),
+                    },
+                )
+                .await?;
+                if tx.affected_rows() > 0 {
+                    blocked += usize::from(failed);
+                    changed.push(id);
+                }
+            }
+            for id in changed.drain(..) {
+                level.extend(
+                    tx.exec::<usize, _, _>(&dependants, params! { "id" => id })
+                        .await?,
+                );
+            }
+            level.sort_unstable();
+            level.dedup();
+        }
+        tx.commit().await?;
+        Ok(blocked)
+    }
+
+    async fn get_waiting_on(&self) -> Result<Vec<(usize, usize)>> {
+        let mut conn = self.get_conn().await?;
+        let pairs = conn
+            .exec(
+                format!(
+                    r"SELECT d.job_id, d.depends_on FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                    WHERE p.status <> :success ORDER BY d.job_id, d.depends_on",
+                    self.table_name
+                ),
+                params! {
+                    "success" => ExperimentStatus::SuccessFinished.to_db_code(),
+                },
+            )
+            .await?;
+        Ok(pairs)
+    }
+
+    /// Claims jobs with a single conditional update, which no other runner
+    /// can interleave with, and then reads back the rows carrying its claim
+    /// token. Jobs fitting one by one but not all together in the capacity
+    /// are made available again.
+    async fn claim_jobs(
+        &self,
+        mut capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>> {
+        let mut conn = self.get_conn().await?;
+        let claim_token = Arc::new(new_claim_token());
+        let order = if shuffle {
+            "ORDER BY priority DESC, RAND()"
+        } else {
+            "ORDER BY priority DESC, id"
+        };
+        let claim = format!(
+            r"UPDATE {0} SET status = :running, claim_token = :claim_token,
+            worker_id = :worker_id
+            WHERE status = :available
+            AND (retry_after IS NULL OR retry_after <= UNIX_TIMESTAMP())
+            AND cpus <= :cpus AND mem <= :mem
+            AND id NOT IN (SELECT job_id FROM (SELECT d.job_id FROM {0}_deps d
+            JOIN {0} p ON p.id = d.depends_on WHERE p.status <> :success) AS waiting){2}
+            {1} LIMIT :limit",
+            self.table_name,
+            order,
+            tags.sql(&self.table_name, tag_placeholder)
+        );
+        let claimed = format!(
+            r"SELECT id, command, timeout, timeout_count, attempts, cpus, mem, cwd FROM {}
+            WHERE claim_token = :claim_token ORDER BY priority DESC, id",
+            self.table_name
+        );
+        // Jobs are claimed in rounds fitting the capacity left, until it runs
+        // out, so smaller jobs after one which does not fit next to the others
+        // still get the room left. Every round takes at least one job, and the
+        // jobs it claimed but could not take stay claimed until the end, so no
+        // round claims them again. The jobs waiting on a dependency are read
+        // through a derived table, as MySQL cannot update a table it reads in
+        // a subquery (error 1093).
+        let mut jobs: Vec<Job> = vec![];
+        let mut left: Vec<Job> = vec![];
+        while capacity.jobs > 0 {
+            conn.exec_drop(
+                &claim,
+                with_tags(
+                    params! {
+                        "running" => ExperimentStatus::Running.to_db_code(),
+                        "claim_token" => claim_token.as_str(),
+                        "worker_id" => worker_id,
+                        "available" => ExperimentStatus::NotRunning.to_db_code(),
+                        "cpus" => capacity.cpus,
+                        "mem" => capacity.mem,
+                        "limit" => capacity.jobs,
+                        "success" => ExperimentStatus::SuccessFinished.to_db_code(),
+                    },
+                    tags,
+                ),
+            )
+            .await?;
+            if conn.affected_rows() == 0 {
+                break;
+            }
+            let round = conn
+                .exec_map(
+                    &claimed,
+                    params! {
+                        "claim_token" => claim_token.as_str(),
+                    },
+                    |(id, command, timeout, timeout_count, attempts, cpus, mem, cwd): (
+                        _,
+                        _,
+                        Option<u64>,
+                        _,
+                        _,
+                        _,
+                        _,
+                        Option<String>,
+                    )| Job {
+                        id,
+                        command: Arc::new(command),
+                        timeout: timeout.map(Duration::from_secs),
+                        timeout_count,
+                        attempts,
+                        cpus,
+                        mem,
+                        cwd: cwd.map(PathBuf::from),
+                        env: vec![],
+                        claim_token: claim_token.clone(),
+                    },
+                )
+                .await?;
+            for job in round {
+                if jobs.iter().chain(&left).any(|j| j.id == job.id) {
+                    continue;
+                }
+                if capacity.take(&job) {
+                    jobs.push(job);
+                } else {
+                    left.push(job);
+                }
+            }
+        }
+        conn.exec_batch(
+            format!(
+                r"UPDATE {} SET status = :available, claim_token = NULL, worker_id = NULL
+                WHERE id = :id AND claim_token = :claim_token",
+                self.table_name
+            ),
+            left.iter().map(|job| {
+                params! {
+                    "available" => ExperimentStatus::NotRunning.to_db_code(),
+                    "id" => job.id,
+                    "claim_token" => claim_token.as_str(),
+                }
+            }),
+        )
+        .await?;
+        for job in &mut jobs {
+            job.env = conn
+                .exec(
+                    format!(
+                        "SELECT name, value FROM {}_env WHERE job_id = :job_id ORDER BY name",
+                        self.table_name
+                    ),
+                    params! {
+                        "job_id" => job.id,
+                    },
+                )
+                .await?;
+        }
+        Ok(jobs)
+    }
+
+    async fn requeue_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let mut conn = self.get_conn().await?;
+        let stmt = conn
+            .prep(format!(
+                "UPDATE {} SET status = :new_status WHERE id = :id AND status <> :running",
+                self.table_name
+            ))
+            .await?;
+        let mut requeued = 0;
+        for id in ids {
+            conn.exec_drop(
+                &stmt,
+                params! {
+                    "new_status" => ExperimentStatus::NotRunning.to_db_code(),
+                    "id" => id,
+                    "running" => ExperimentStatus::Running.to_db_code(),
+                },
+            )
+            .await?;
+            requeued += conn.affected_rows() as usize;
+        }
+        Ok(requeued)
+    }
+
+    async fn release_job(&self, id: usize, claim_token: &str) -> Result<bool> {
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(
+            format!(
+                r"UPDATE {} SET status = :new_status
+                WHERE id = :id AND status = :running AND claim_token = :claim_token",
+                self.table_name
+            ),
+            params! {
+                "new_status" => ExperimentStatus::NotRunning.to_db_code(),
+                "id" => id,
+                "running" => ExperimentStatus::Running.to_db_code(),
+                "claim_token" => claim_token,
+            },
+        )
+        .await?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn record_attempt(
+        &self,
+        id: usize,
+        claim_token: &str,
+        new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
+    ) -> Result<bool> {
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(
+            format!(
+                r"UPDATE {} SET status = :new_status, attempts = attempts + 1,
+                timeout_count = timeout_count + :timed_out,
+                retry_after = :retry_delay + UNIX_TIMESTAMP()
+                WHERE id = :id AND status = :running AND claim_token = :claim_token",
+                self.table_name
+            ),
+            params! {
+                "new_status" => new_status.to_db_code(),
+                "timed_out" => timed_out as usize,
+                "retry_delay" => retry_delay.map(|d| d.as_secs()),
+                "id" => id,
+                "running" => ExperimentStatus::Running.to_db_code(),
+                "claim_token" => claim_token,
+            },
+        )
+        .await?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn reset_jobs(
+        &self,
+        status: Option<ExperimentStatus>,
+        tags: &TagFilter,
+    ) -> Result<Vec<usize>> {
+        let selected = format!(
+            "(:status IS NULL OR status = :status){}",
+            tags.sql(&self.table_name, tag_placeholder)
+        );
+        let params = with_tags(
+            params! {
+                "new_status" => ExperimentStatus::NotRunning.to_db_code(),
+                "status" => status.map(|s| s.to_db_code()),
+            },
+            tags,
+        );
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let ids = tx
+            .exec(
+                format!(
+                    "SELECT id FROM {} WHERE {} FOR UPDATE",
+                    self.table_name, selected
+                ),
+                params.clone(),
+            )
+            .await?;
+        tx.exec_drop(
+            format!(
+                r"UPDATE {} SET status = :new_status, attempts = 0, timeout_count = 0,
+                    retry_after = NULL WHERE {}",
+                self.table_name, selected
+            ),
+            params,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<usize> {
+        let mut conn = self.get_conn().await?;
+        match jobs {
+            JobSelection::Ids(ids) => {
+                let mut tx = conn.start_transaction(TxOpts::default()).await?;
+                let mut changed = 0;
+                for id in ids {
+                    tx.exec_drop(
+                        format!(
+                            "UPDATE {} SET priority = :priority WHERE id = :id",
+                            self.table_name
+                        ),
+                        params! {
+                            "priority" => priority,
+                            "id" => id,
+                        },
+                    )
+                    .await?;
+                    changed += tx.affected_rows() as usize;
+                }
+                tx.commit().await?;
+                Ok(changed)
+            }
+            JobSelection::Matching(text) => {
+                conn.exec_drop(
+                    format!(
+                        r"UPDATE {} SET priority = :priority
+                        WHERE INSTR(CAST(command AS BINARY), CAST(:text AS BINARY)) > 0",
+                        self.table_name
+                    ),
+                    params! {
+                        "priority" => priority,
+                        "text" => text,
+                    },
+                )
+                .await?;
+                Ok(conn.affected_rows() as usize)
+            }
+        }
+    }
+
+    async fn count_fitting_jobs(&self, host: Capacity, tags: &TagFilter) -> Result<usize> {
+        let mut conn = self.get_conn().await?;
+        let job_count: Option<usize> = conn
+            .exec_first(
+                format!(
+                    r"WITH RECURSIVE stuck(id) AS (
+                    SELECT id FROM {0}
+                    WHERE status = :status AND NOT (cpus <= :cpus AND mem <= :mem{1})
+                    UNION
+                    SELECT d.job_id FROM {0}_deps d JOIN stuck s ON s.id = d.depends_on
+                    JOIN {0} ON {0}.id = d.job_id WHERE {0}.status = :status)
+                    SELECT COUNT(*) FROM {0}
+                    WHERE status = :status AND cpus <= :cpus AND mem <= :mem{1}
+                    AND id NOT IN (SELECT id FROM stuck)",
+                    self.table_name,
+                    tags.sql(&self.table_name, tag_placeholder)
+                ),
+                with_tags(
+                    params! {
+                        "status" => ExperimentStatus::NotRunning.to_db_code(),
+                        "cpus" => host.cpus,
+                        "mem" => host.mem,
+                    },
+                    tags,
+                ),
+            )
+            .await?;
+        Ok(job_count.unwrap_or_default())
+    }
+
+    async fn count_jobs_by_status(
+        &self,
+        tags: &TagFilter,
+    ) -> Result<Vec<(ExperimentStatus, usize)>> {
+        let mut conn = self.get_conn().await?;
+        let counts = conn
+            .exec_map(
+                format!(
+                    "SELECT status, COUNT(*) from {} WHERE 1 = 1{} GROUP BY status",
+                    self.table_name,
+                    tags.sql(&self.table_name, tag_placeholder)
+                ),
+                with_tags(Params::Empty, tags),
+                |(s, c)| (ExperimentStatus::new(s), c),
+            )
+            .await?;
+        Ok(counts)
+    }
+
+    async fn record_run_stats(
+        &self,
+        id: usize,
+        claim_token: &str,
+        stats: RunStats,
+    ) -> Result<bool> {
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(
+            format!(
+                r"UPDATE {} SET exit_code = :exit_code, term_signal = :signal,
+                start_time = :start_time, end_time = :end_time, wall_time = :wall_time,
+                user_time = :user_time, sys_time = :sys_time, max_rss = :max_rss,
+                launch_error = :launch_error
+                WHERE id = :id AND status = :running AND claim_token = :claim_token",
+                self.table_name
+            ),
+            params! {
+                "exit_code" => stats.exit_code,
+                "signal" => stats.signal,
+                "start_time" => stats.start_time,
+                "end_time" => stats.end_time,
+                "wall_time" => stats.wall_time,
+                "user_time" => stats.user_time,
+                "sys_time" => stats.sys_time,
+                "max_rss" => stats.max_rss,
+                "launch_error" => stats.launch_error,
+                "id" => id,
+                "running" => ExperimentStatus::Running.to_db_code(),
+                "claim_token" => claim_token,
+            },
+        )
+        .await?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn get_all_jobs(&self) -> Result<Vec<JobInfo>> {
+        let mut conn = self.get_conn().await?;
+        let jobs = conn
+            .query_map(
+                format!(
+                    "SELECT {} from {} ORDER BY id",
+                    JOB_INFO_COLUMNS, self.table_name
+                ),
+                |mut row: Row| JobInfo {
+                    id: row.take(0).unwrap_or_default(),
+                    command: row.take(1).unwrap_or_default(),
+                    status: ExperimentStatus::new(row.take(2).unwrap_or_default()),
+                    attempts: row.take(3).unwrap_or_default(),
+                    run: RunStats {
+                        exit_code: row.take(4).flatten(),
+                        signal: row.take(5).flatten(),
+                        start_time: row.take(6).flatten(),
+                        end_time: row.take(7).flatten(),
+                        wall_time: row.take(8).flatten(),
+                        user_time: row.take(9).flatten(),
+                        sys_time: row.take(10).flatten(),
+                        max_rss: row.take(11).flatten(),
+                        launch_error: row.take(12).flatten(),
+                    },
+                },
+            )
+            .await?;
+        Ok(jobs)
+    }
+
+    async fn get_param_values(&self, name: &str) -> Result<Vec<(usize, String)>> {
+        let mut conn = self.get_conn().await?;
+        let values = conn
+            .exec(
+                format!(
+                    "SELECT job_id, value FROM {}_params WHERE name = :name ORDER BY job_id",
+                    self.table_name
+                ),
+                params! {
+                    "name" => name,
+                },
+            )
+            .await?;
+        Ok(values)
+    }
+
+    async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(WORKERS_TABLE).await?;
+        conn.exec_drop(
+            r"INSERT INTO dpr_workers (worker_id, table_name, heartbeat, lease)
+            VALUES (:worker_id, :table_name, UNIX_TIMESTAMP(), :lease)
+            ON DUPLICATE KEY UPDATE heartbeat = VALUES(heartbeat), lease = VALUES(lease)",
+            params! {
+                "worker_id" => worker_id,
+                "table_name" => self.table_name.as_str(),
+                "lease" => lease.as_secs(),
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn reclaim_lost_jobs(&self, max_attempts: Option<usize>) -> Result<Vec<usize>> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(WORKERS_TABLE).await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let lost = "table_name = :table_name AND heartbeat + lease < UNIX_TIMESTAMP()";
+        let reclaimed = tx
+            .exec(
+                format!(
+                    r"SELECT id FROM {0} WHERE status = :status
+                    AND worker_id IN (SELECT worker_id FROM dpr_workers WHERE {1}) FOR UPDATE",
+                    self.table_name, lost
+                ),
+                params! {
+                    "status" => ExperimentStatus::Running.to_db_code(),
+                    "table_name" => self.table_name.as_str(),
+                },
+            )
+            .await?;
+        tx.exec_drop(
+            format!(
+                r"UPDATE {0} SET lost_count = lost_count + 1,
+                status = CASE WHEN attempts + 1 >= :max_attempts THEN :failed ELSE :new_status END,
+                attempts = attempts + 1
+                WHERE status = :status
+                AND worker_id IN (SELECT worker_id FROM dpr_workers WHERE {1})",
+                self.table_name, lost
+            ),
+            params! {
+                "new_status" => ExperimentStatus::NotRunning.to_db_code(),
+                "status" => ExperimentStatus::Running.to_db_code(),
+                "max_attempts" => max_attempts,
+                "failed" => ExperimentStatus::FailedFinished.to_db_code(),
+                "table_name" => self.table_name.as_str(),
+            },
+        )
+        .await?;
+        tx.exec_drop(
+            format!("DELETE FROM dpr_workers WHERE {}", lost),
+            params! {
+                "table_name" => self.table_name.as_str(),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(reclaimed)
+    }
+
+    async fn remove_worker(&self, worker_id: &str) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(WORKERS_TABLE).await?;
+        conn.exec_drop(
+            "DELETE FROM dpr_workers WHERE worker_id = :worker_id",
+            params! {
+                "worker_id" => worker_id,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_setting(&self, name: &str) -> Result<Option<String>> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(SETTINGS_TABLE).await?;
+        let value = conn
+            .exec_first(
+                r"SELECT value FROM dpr_table_settings
+                WHERE table_name = :table_name AND name = :name",
+                params! {
+                    "table_name" => self.table_name.as_str(),
+                    "name" => name,
+                },
+            )
+            .await?;
+        Ok(value)
+    }
+
+    async fn set_setting(&self, name: &str, value: Option<String>) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        conn.query_drop(SETTINGS_TABLE).await?;
+        match value {
+            Some(value) => {
+                conn.exec_drop(
+                    r"INSERT INTO dpr_table_settings (table_name, name, value)
+                    VALUES (:table_name, :name, :value)
+                    ON DUPLICATE KEY UPDATE value = VALUES(value)",
+                    params! {
+                        "table_name" => self.table_name.as_str(),
+                        "name" => name,
+                        "value" => value,
+                    },
+                )
+                .await?
+            }
+            None => {
+                conn.exec_drop(
+                    r"DELETE FROM dpr_table_settings
+                    WHERE table_name = :table_name AND name = :name",
+                    params! {
+                        "table_name" => self.table_name.as_str(),
+                        "name" => name,
+                    },
+                )
+                .await?
+            }
+        };
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.pool.clone().disconnect().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1064 @@
+use super::{
+    config::SslMode,
+    failed_status_codes, fill_content_hashes, legacy_schema_version, new_claim_token, Capacity,
+    DatabaseConfig, DatabaseError, Dependency, ExperimentStatus, Job, JobInfo, JobSelection,
+    JobStore,
+    MigrationStep::{self, DropStatusCheck, FillContentHashes, Sql},
+    RunStats, TableEntry, TagFilter, JOB_INFO_COLUMNS, SCHEMA_VERSION,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use deadpool_postgres::{Client, GenericClient, Manager, ManagerConfig, Pool, RecyclingMethod};
+use native_tls::{Certificate, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio_postgres::{types::ToSql, NoTls};
+
+const SCHEMA_VERSIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_schema_versions (
+    table_name VARCHAR(64) NOT NULL PRIMARY KEY,
+    version INT NOT NULL)";
+
+const SETTINGS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_table_settings (
+    table_name VARCHAR(64) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    value VARCHAR(500) NOT NULL,
+    PRIMARY KEY (table_name, name))";
+
+const WORKERS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_workers (
+    worker_id VARCHAR(64) NOT NULL PRIMARY KEY,
+    table_name VARCHAR(64) NOT NULL,
+    heartbeat BIGINT NOT NULL,
+    lease BIGINT NOT NULL)";
+
+/// Current unix time, in seconds, as seen by the database.
+const NOW: &str = "CAST(EXTRACT(EPOCH FROM now()) AS BIGINT)";
+
+/// Statements upgrading a table from schema version `i + 1` to `i + 2`.
+const MIGRATIONS: [&[MigrationStep]; SCHEMA_VERSION - 1] = [
+    // 2: row-level claims
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN claim_token CHAR(32)"),
+        Sql("CREATE INDEX {table}_status ON {table} (status)"),
+        Sql("CREATE INDEX {table}_claim_token ON {table} (claim_token)"),
+    ],
+    // 3: per-job timeouts, in seconds
+    &[Sql("ALTER TABLE {table} ADD COLUMN timeout INT")],
+    // 4: timeout ladder tiers
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN timeout_count INT NOT NULL DEFAULT 0",
+    )],
+    // 5: exit status, times and resource usage of the last run
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN exit_code INT"),
+        Sql("ALTER TABLE {table} ADD COLUMN term_signal INT"),
+        Sql("ALTER TABLE {table} ADD COLUMN start_time BIGINT"),
+        Sql("ALTER TABLE {table} ADD COLUMN end_time BIGINT"),
+        Sql("ALTER TABLE {table} ADD COLUMN wall_time DOUBLE PRECISION"),
+        Sql("ALTER TABLE {table} ADD COLUMN user_time DOUBLE PRECISION"),
+        Sql("ALTER TABLE {table} ADD COLUMN sys_time DOUBLE PRECISION"),
+        Sql("ALTER TABLE {table} ADD COLUMN max_rss BIGINT"),
+    ],
+    // 6: killed and launch error statuses
+    &[
+        DropStatusCheck,
+        Sql("ALTER TABLE {table} ADD CONSTRAINT {table}_status_check CHECK(status<7)"),
+        Sql("ALTER TABLE {table} ADD COLUMN launch_error TEXT"),
+    ],
+    // 7: workers holding the jobs, and jobs they lost
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN worker_id VARCHAR(64)"),
+        Sql("ALTER TABLE {table} ADD COLUMN lost_count INT NOT NULL DEFAULT 0"),
+    ],
+    // 8: attempts, and when a retried job can be claimed again
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN attempts INT NOT NULL DEFAULT 0"),
+        Sql("ALTER TABLE {table} ADD COLUMN retry_after BIGINT"),
+    ],
+    // 9: cores and memory, in MiB, needed by each job
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cpus INT NOT NULL DEFAULT 1"),
+        Sql("ALTER TABLE {table} ADD COLUMN mem BIGINT NOT NULL DEFAULT 0"),
+    ],
+    // 10: priorities, the highest claimed first
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN priority INT NOT NULL DEFAULT 0",
+    )],
+    // 11: labels, dependencies and the blocked status
+    &[
+        DropStatusCheck,
+        Sql("ALTER TABLE {table} ADD CONSTRAINT {table}_status_check CHECK(status<8)"),
+        Sql("ALTER TABLE {table} ADD COLUMN label VARCHAR(255)"),
+        Sql("CREATE INDEX {table}_label ON {table} (label)"),
+        Sql(r"CREATE TABLE {table}_deps (
+            job_id INT NOT NULL,
+            depends_on INT NOT NULL,
+            PRIMARY KEY (job_id, depends_on))"),
+        Sql("CREATE INDEX {table}_deps_depends_on ON {table}_deps (depends_on)"),
+    ],
+    // 12: tags
+    &[
+        Sql(r"CREATE TABLE {table}_tags (
+            job_id INT NOT NULL,
+            tag VARCHAR(64) NOT NULL,
+            PRIMARY KEY (job_id, tag))"),
+        Sql("CREATE INDEX {table}_tags_tag ON {table}_tags (tag)"),
+    ],
+    // 13: values of the template parameters of each job
+    &[Sql(r"CREATE TABLE {table}_params (
+        job_id INT NOT NULL,
+        name VARCHAR(64) NOT NULL,
+        value VARCHAR(500) NOT NULL,
+        PRIMARY KEY (job_id, name))")],
+    // 14: working directories and environment variables
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cwd VARCHAR(500)"),
+        Sql(r"CREATE TABLE {table}_env (
+            job_id INT NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (job_id, name))"),
+    ],
+    // 15: content hashes, unique so a job cannot be loaded twice
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN content_hash CHAR(64)"),
+        FillContentHashes,
+        Sql("CREATE UNIQUE INDEX {table}_content_hash ON {table} (content_hash)"),
+    ],
+];
+
+/// Binds the tags of a filter after the other parameters of a statement.
+fn with_tags<'a>(
+    mut params: Vec<&'a (dyn ToSql + Sync)>,
+    tags: &'a TagFilter,
+) -> Vec<&'a (dyn ToSql + Sync)> {
+    params.extend(tags.values().map(|tag| tag as &(dyn ToSql + Sync)));
+    params
+}
+
+/// PostgreSQL storage for the experiment tables. Claims lock the selected rows
+/// with `FOR UPDATE SKIP LOCKED`, so concurrent runners never wait on each
+/// other and never hand out the same job twice.
+#[derive(Clone, Debug)]
+pub(crate) struct PostgresDatabase {
+    pool: Pool,
+    table_name: Arc<String>,
+}
+
+impl PostgresDatabase {
+    pub(crate) fn new(db_config: &DatabaseConfig, table_name: Arc<String>) -> Result<Self> {
+        let mut pg_config = tokio_postgres::Config::new();
+        pg_config
+            .user(db_config.get_user()?)
+            .password(db_config.get_password()?)
+            .dbname(&db_config.get_database()?);
+        match db_config.get_socket() {
+            Some(socket_dir) => pg_config.host_path(socket_dir),
+            None => pg_config.host(db_config.get_host()?),
+        };
+        if let Some(port) = db_config.get_port() {
+            pg_config.port(port);
+        }
+        if let Some(connect_timeout) = db_config.get_connect_timeout() {
+            pg_config.connect_timeout(connect_timeout);
+        }
+        let manager_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+        let ssl_mode = db_config.get_ssl_mode();
+        let manager = if ssl_mode == SslMode::Disabled {
+            pg_config.ssl_mode(tokio_postgres::config::SslMode::Disable);
+            Manager::from_config(pg_config, NoTls, manager_config)
+        } else {
+            pg_config.ssl_mode(tokio_postgres::config::SslMode::Require);
+            let mut connector = TlsConnector::builder();
+            connector
+                .danger_accept_invalid_certs(ssl_mode == SslMode::Required)
+                .danger_accept_invalid_hostnames(ssl_mode != SslMode::VerifyIdentity);
+            if let Some(ssl_ca) = db_config.get_ssl_ca() {
+                connector.add_root_certificate(Certificate::from_pem(&std::fs::read(ssl_ca)?)?);
+            }
+            let tls = MakeTlsConnector::new(connector.build()?);
+            Manager::from_config(pg_config, tls, manager_config)
+        };
+        if db_config.get_pool_min().is_some() {
+            eprintln!("dpr: `pool_min` is ignored by the PostgreSQL backend, which opens connections on demand");
+        }
+        let mut pool = Pool::builder(manager);
+        if let Some(pool_max) = db_config.get_pool_max() {
+            pool = pool.max_size(pool_max);
+        }
+        Ok(Self {
+            pool: pool.build()?,
+            table_name,
+        })
+    }
+
+    async fn set_schema_version(&self, client: &impl GenericClient, version: usize) -> Result<()> {
+        client.batch_execute(SCHEMA_VERSIONS_TABLE).await?;
+        client
+            .execute(
+                r"INSERT INTO dpr_schema_versions (table_name, version) VALUES ($1, $2)
+                ON CONFLICT (table_name) DO UPDATE SET version = excluded.version",
+                &[&self.table_name.as_str(), &(version as i32)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Applies the migration steps from version `from` up to `to`, each one
+    /// in its own transaction.
+    async fn migrate_from(&self, client: &mut Client, from: usize, to: usize) -> Result<()> {
+        for (i, step) in MIGRATIONS.iter().enumerate().take(to - 1).skip(from - 1) {
+            let tx = client.transaction().await?;
+            for statement in step.iter() {
+                match statement {
+                    Sql(sql) => {
+                        tx.batch_execute(&sql.replace("{table}", &self.table_name))
+                            .await?
+                    }
+                    FillContentHashes => self.fill_content_hashes(&tx).await?,
+                    DropStatusCheck => {
+                        tx.batch_execute(&format!(
+                            "ALTER TABLE {0} DROP CONSTRAINT IF EXISTS {0}_status_check",
+                            self.table_name
+                        ))
+                        .await?
+                    }
+                }
+            }
+            self.set_schema_version(&tx, i + 2).await?;
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    async fn fill_content_hashes(&self, client: &impl GenericClient) -> Result<()> {
+        let jobs = client
+            .query(
+                &format!(
+                    "SELECT id, command, cwd FROM {} ORDER BY id",
+                    self.table_name
+                ),
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| (row.get::<_, i32>(0) as usize, row.get(1), row.get(2)))
+            .collect();
+        let env = client
+            .query(
+                &format!("SELECT job_id, name, value FROM {}_env", self.table_name),
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| (row.get::<_, i32>(0) as usize, row.get(1), row.get(2)))
+            .collect();
+        let stmt = client
+            .prepare(&format!(
+                "UPDATE {} SET content_hash = $1 WHERE id = $2",
+                self.table_name
+            ))
+            .await?;
+        for (content_hash, id) in fill_content_hashes(jobs, env) {
+            client
+                .execute(&stmt, &[&content_hash, &(id as i32)])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobStore for PostgresDatabase {
+    async fn create_table(&self, version: usize) -> Result<()> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.batch_execute(&format!(
+            r"DROP TABLE IF EXISTS {0};
+            DROP TABLE IF EXISTS {0}_deps;
+            DROP TABLE IF EXISTS {0}_tags;
+            DROP TABLE IF EXISTS {0}_params;
+            DROP TABLE IF EXISTS {0}_env;
+            CREATE TABLE {0} (
+                id SERIAL PRIMARY KEY,
+                command VARCHAR(500) NOT NULL,
+                status INT NOT NULL,
+                CHECK(status<5));",
+            self.table_name
+        ))
+        .await?;
+        self.set_schema_version(&tx, 1).await?;
+        tx.batch_execute(SETTINGS_TABLE).await?;
+        tx.execute(
+            "DELETE FROM dpr_table_settings WHERE table_name = $1",
+            &[&self.table_name.as_str()],
+        )
+        .await?;
+        tx.commit().await?;
+        self.migrate_from(&mut client, 1, version).await
+    }
+
+    async fn schema_version(&self) -> Result<usize> {
+        let client = self.pool.get().await?;
+        client
+            .batch_execute(&format!("SELECT id FROM {} LIMIT 0", self.table_name))
+            .await?;
+        client.batch_execute(SCHEMA_VERSIONS_TABLE).await?;
+        let row = client
+            .query_opt(
+                "SELECT version FROM dpr_schema_versions WHERE table_name = $1",
+                &[&self.table_name.as_str()],
+            )
+            .await?;
+        match row {
+            Some(row) => Ok(row.get::<_, i32>(0) as usize),
+            None => {
+                let has_claim_token = client
+                    .batch_execute(&format!(
+                        "SELECT claim_token FROM {} LIMIT 0",
+                        self.table_name
+                    ))
+                    .await
+                    .is_ok();
+                Ok(legacy_schema_version(has_claim_token))
+            }
+        }
+    }
+
+    async fn migrate(&self, from: usize) -> Result<()> {
+        let mut client = self.pool.get().await?;
+        self.migrate_from(&mut client, from, SCHEMA_VERSION).await
+    }
+
+    async fn insert_commands(&self, commands: Vec<String>) -> Result<()> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let stmt = tx
+            .prepare(&format!(
+                "INSERT INTO {} (command, status) VALUES ($1, $2)",
+                self.table_name
+            ))
+            .await?;
+        for command in commands {
+            tx.execute(
+                &stmt,
+                &[
+                    &command,
+                    &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                ],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_jobs(&self, table_entries: Vec<TableEntry>) -> Result<Vec<usize>> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let stmt = tx
+            .prepare(&format!(
+                r"INSERT INTO {} (command, status, timeout, cpus, mem, priority, label, cwd,
+                content_hash) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+                self.table_name
+            ))
+            .await?;
+        let tag_stmt = tx
+            .prepare(&format!(
+                "INSERT INTO {}_tags (job_id, tag) VALUES ($1, $2)",
+                self.table_name
+            ))
+            .await?;
+        let param_stmt = tx
+            .prepare(&format!(
+                "INSERT INTO {}_params (job_id, name, value) VALUES ($1, $2, $3)",
+                self.table_name
+            ))
+            .await?;
+        let env_stmt = tx
+            .prepare(&format!(
+                "INSERT INTO {}_env (job_id, name, value) VALUES ($1, $2, $3)",
+                self.table_name
+            ))
+            .await?;
+        let mut ids = Vec::with_capacity(table_entries.len());
+        for t in table_entries {
+            let timeout = t
+                .timeout
+                .map(|d| {
+                    i32::try_from(d.as_secs())
+                        .map_err(|_| DatabaseError::TimeoutTooLarge(d.as_secs()))
+                })
+                .transpose()?;
+            let row = tx
+                .query_one(
+                    &stmt,
+                    &[
+                        &t.command,
+                        &(t.status.to_db_code() as i32),
+                        &timeout,
+                        &(t.cpus as i32),
+                        &(t.mem as i64),
+                        &t.priority,
+                        &t.label,
+                        &t.cwd,
+                        &t.content_hash(),
+                    ],
+                )
+                .await?;
+            let id: i32 = row.get(0);
+            for tag in &t.tags {
+                tx.execute(&tag_stmt, &[&id, tag]).await?;
+            }
+            for (name, value) in &t.params {
+                tx.execute(&param_stmt, &[&id, name, value]).await?;
+            }
+            for (name, value) in &t.env {
+                tx.execute(&env_stmt, &[&id, name, value]).await?;
+            }
+            ids.push(id as usize);
+        }
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    async fn get_content_hashes(&self) -> Result<Vec<(String, usize, ExperimentStatus)>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT content_hash, id, status FROM {} WHERE content_hash IS NOT NULL",
+                    self.table_name
+                ),
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                (
+                    row.get(0),
+                    row.get::<_, i32>(1) as usize,
+                    ExperimentStatus::new(row.get::<_, i32>(2) as usize),
+                )
+            })
+            .collect())
+    }
+
+    async fn find_jobs(&self, dependency: &Dependency) -> Result<Vec<usize>> {
+        let client = self.pool.get().await?;
+        let rows = match dependency {
+            Dependency::Id(id) => {
+                client
+                    .query(
+                        &format!("SELECT id FROM {} WHERE id = $1", self.table_name),
+                        &[&i32::try_from(*id).unwrap_or(i32::MAX)],
+                    )
+                    .await?
+            }
+            Dependency::Label(label) => {
+                client
+                    .query(
+                        &format!(
+                            "SELECT id FROM {} WHERE label = $1 ORDER BY id",
+                            self.table_name
+                        ),
+                        &[label],
+                    )
+                    .await?
+            }
+        };
+        Ok(rows
+            .iter()
+            .map(|row| row.get::<_, i32>(0) as usize)
+            .collect())
+    }
+
+    async fn insert_dependencies(&self, dependencies: Vec<(usize, usize)>) -> Result<()> {
+        let client = self.pool.get().await?;
+        let (job_ids, depends_on): (Vec<i32>, Vec<i32>) = dependencies
+            .into_iter()
+            .map(|(job_id, depends_on)| (job_id as i32, depends_on as i32))
+            .unzip();
+        client
+            .execute(
+                &format!(
+                    r"INSERT INTO {}_deps (job_id, depends_on)
+                    SELECT * FROM UNNEST($1::INT[], $2::INT[])",
+                    self.table_name
+                ),
+                &[&job_ids, &depends_on],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let failed_dependency = format!(
+            r"EXISTS (SELECT 1 FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+            WHERE d.job_id = {0}.id AND p.status IN ({1}))",
+            self.table_name,
+            failed_status_codes()
+        );
+        let available = ExperimentStatus::NotRunning.to_db_code() as i32;
+        let blocked_status = ExperimentStatus::Blocked.to_db_code() as i32;
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let unblock = tx
+            .prepare(&format!(
+                r"UPDATE {} SET status = $1
+                WHERE id = ANY($3) AND status = $2 AND NOT {} RETURNING id",
+                self.table_name, failed_dependency
+            ))
+            .await?;
+        let block = tx
+            .prepare(&format!(
+                r"UPDATE {} SET status = $2
+                WHERE id = ANY($3) AND status = $1 AND {} RETURNING id",
+                self.table_name, failed_dependency
+            ))
+            .await?;
+        let dependants = tx
+            .prepare(&format!(
+                "SELECT DISTINCT job_id FROM {}_deps WHERE depends_on = ANY($1)",
+                self.table_name
+            ))
+            .await?;
+        // The given jobs are checked and all their dependants after them,
+        // then only the dependants of the jobs which changed.
+        let mut level: Vec<i32> = ids.into_iter().map(|id| id as i32).collect();
+        let mut changed = level.clone();
+        let mut blocked = 0;
+        while !level.is_empty() {
+            let codes: [&(dyn ToSql + Sync); 3] = [&available, &blocked_status, &level];
+            let newly_blocked = tx.query(&block, &codes).await?;
+            blocked += newly_blocked.len();
+            let unblocked = tx.query(&unblock, &codes).await?;
+            changed.extend(
+                newly_blocked
+                    .iter()
+                    .chain(&unblocked)
+                    .map(|row| row.get::<_, i32>(0)),
+            );
+            level = tx
+                .query(&dependants, &[&changed])
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+            changed.clear();
+        }
+        tx.commit().await?;
+        Ok(blocked)
+    }
+
+    async fn get_waiting_on(&self) -> Result<Vec<(usize, usize)>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    r"SELECT d.job_id, d.depends_on FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                    WHERE p.status <> $1 ORDER BY d.job_id, d.depends_on",
+                    self.table_name
+                ),
+                &[&(ExperimentStatus::SuccessFinished.to_db_code() as i32)],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<_, i32>(0) as usize, row.get::<_, i32>(1) as usize))
+            .collect())
+    }
+
+    async fn claim_jobs(
+        &self,
+        mut capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>> {
+        let order = if shuffle {
+            "ORDER BY priority DESC, random()"
+        } else {
+            "ORDER BY priority DESC, id"
+        };
+        let claim_token = Arc::new(new_claim_token());
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let candidates = tx
+            .prepare(&format!(
+                r"SELECT id, command, timeout, timeout_count, attempts, cpus, mem, cwd FROM {0}
+                WHERE status = $1 AND (retry_after IS NULL OR retry_after <= {1})
+                AND cpus <= $2 AND mem <= $3 AND NOT id = ANY($6)
+                AND NOT EXISTS (SELECT 1 FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                WHERE d.job_id = {0}.id AND p.status <> $5){3}
+                {2} LIMIT $4 FOR UPDATE OF {0} SKIP LOCKED",
+                self.table_name,
+                NOW,
+                order,
+                tags.sql(&self.table_name, |i| format!("${}", i + 7))
+            ))
+            .await?;
+        // Candidates are read in rounds fitting the capacity left, until it
+        // runs out, so smaller jobs after one which does not fit next to the
+        // others still get the room left. Every round takes at least one job.
+        let mut jobs: Vec<Job> = vec![];
+        let mut seen: Vec<i32> = vec![];
+        while capacity.jobs > 0 {
+            let limit = capacity.jobs as i64;
+            let rows = tx
+                .query(
+                    &candidates,
+                    &with_tags(
+                        vec![
+                            &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                            &i32::try_from(capacity.cpus).unwrap_or(i32::MAX),
+                            &i64::try_from(capacity.mem).unwrap_or(i64::MAX),
+                            &limit,
+                            &(ExperimentStatus::SuccessFinished.to_db_code() as i32),
+                            &seen,
+                        ],
+                        tags,
+                    ),
+                )
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+            for row in &rows {
+                let job = Job {
+                    id: row.get::<_, i32>(0) as usize,
+                    command: Arc::new(row.get(1)),
+                    timeout: row
+                        .get::<_, Option<i32>>(2)
+                        .map(|secs| Duration::from_secs(secs as u64)),
+                    timeout_count: row.get::<_, i32>(3) as usize,
+                    attempts: row.get::<_, i32>(4) as usize,
+                    cpus: row.get::<_, i32>(5) as usize,
+                    mem: row.get::<_, i64>(6) as u64,
+                    cwd: row.get::<_, Option<String>>(7).map(PathBuf::from),
+                    env: vec![],
+                    claim_token: claim_token.clone(),
+                };
+                seen.push(job.id as i32);
+                if capacity.take(&job) {
+                    jobs.push(job);
+                }
+            }
+        }
+        let ids: Vec<i32> = jobs.iter().map(|job| job.id as i32).collect();
+        let env_rows = tx
+            .query(
+                &format!(
+                    "SELECT job_id, name, value FROM {}_env WHERE job_id = ANY($1) ORDER BY name",
+                    self.table_name
+                ),
+                &[&ids],
+            )
+            .await?;
+        for row in env_rows {
+            let id = row.get::<_, i32>(0) as usize;
+            if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+                job.env.push((row.get(1), row.get(2)));
+            }
+        }
+        tx.execute(
+            &format!(
+                r"UPDATE {} SET status = $1, claim_token = $2, worker_id = $3
+                WHERE id = ANY($4)",
+                self.table_name
+            ),
+            &[
+                &(ExperimentStatus::Running.to_db_code() as i32),
+                &claim_token.as_str(),
+                &worker_id,
+                &ids,
+            ],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(jobs)
+    }
+
+    async fn requeue_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        let client = self.pool.get().await?;
+        let ids: Vec<i32> = ids.into_iter().map(|id| id as i32).collect();
+        let requeued = client
+            .execute(
+                &format!(
+                    "UPDATE {} SET status = $1 WHERE id = ANY($2) AND status <> $3",
+                    self.table_name
+                ),
+                &[
+                    &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                    &ids,
+                    &(ExperimentStatus::Running.to_db_code() as i32),
+                ],
+            )
+            .await?;
+        Ok(requeued as usize)
+    }
+
+    async fn release_job(&self, id: usize, claim_token: &str) -> Result<bool> {
+        let client = self.pool.get().await?;
+        let changed = client
+            .execute(
+                &format!(
+                    "UPDATE {} SET status = $1 WHERE id = $2 AND status = $3 AND claim_token = $4",
+                    self.table_name
+                ),
+                &[
+                    &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                    &(id as i32),
+                    &(ExperimentStatus::Running.to_db_code() as i32),
+                    &claim_token,
+                ],
+            )
+            .await?;
+        Ok(changed > 0)
+    }
+
+    async fn record_attempt(
+        &self,
+        id: usize,
+        claim_token: &str,
+        new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
+    ) -> Result<bool> {
+        let client = self.pool.get().await?;
+        let changed = client
+            .execute(
+                &format!(
+                    r"UPDATE {} SET status = $1, attempts = attempts + 1,
+                    timeout_count = timeout_count + $2, retry_after = $3 + {}
+                    WHERE id = $4 AND status = $5 AND claim_token = $6",
+                    self.table_name, NOW
+                ),
+                &[
+                    &(new_status.to_db_code() as i32),
+                    &(timed_out as i32),
+                    &retry_delay.map(|d| d.as_secs() as i64),
+                    &(id as i32),
+                    &(ExperimentStatus::Running.to_db_code() as i32),
+                    &claim_token,
+                ],
+            )
+            .await?;
+        Ok(changed > 0)
+    }
+
+    async fn reset_jobs(
+        &self,
+        status: Option<ExperimentStatus>,
+        tags: &TagFilter,
+    ) -> Result<Vec<usize>> {
+        let client = self.pool.get().await?;
+        let ids = client
+            .query(
+                &format!(
+                    r"UPDATE {} SET status = $1, attempts = 0, timeout_count = 0, retry_after = NULL
+                    WHERE ($2::INT IS NULL OR status = $2){} RETURNING id",
+                    self.table_name,
+                    tags.sql(&self.table_name, |i| format!("${}", i + 3))
+                ),
+                &with_tags(
+                    vec![
+                        &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                        &status.map(|s| s.to_db_code() as i32),
+                    ],
+                    tags,
+                ),
+            )
+            .await?;
+        Ok(ids
+            .iter()
+            .map(|row| row.get::<_, i32>(0) as usize)
+            .collect())
+    }
+
+    async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<usize> {
+        let client = self.pool.get().await?;
+        let changed = match jobs {
+            JobSelection::Ids(ids) => {
+                let ids: Vec<i32> = ids.into_iter().map(|id| id as i32).collect();
+                client
+                    .execute(
+                        &format!(
+                            "UPDATE {} SET priority = $1 WHERE id = ANY($2)",
+                            self.table_name
+                        ),
+                        &[&priority, &ids],
+                    )
+                    .await?
+            }
+            JobSelection::Matching(text) => {
+                client
+                    .execute(
+                        &format!(
+                            "UPDATE {} SET priority = $1 WHERE strpos(command, $2) > 0",
+                            self.table_name
+                        ),
+                        &[&priority, &text],
+                    )
+                    .await?
+            }
+        };
+        Ok(changed as usize)
+    }
+
+    async fn count_fitting_jobs(&self, host: Capacity, tags: &TagFilter) -> Result<usize> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_one(
+                &format!(
+                    r"WITH RECURSIVE stuck(id) AS (
+                    SELECT id FROM {0} WHERE status = $1 AND NOT (cpus <= $2 AND mem <= $3{1})
+                    UNION
+                    SELECT d.job_id FROM {0}_deps d JOIN stuck s ON s.id = d.depends_on
+                    JOIN {0} ON {0}.id = d.job_id WHERE {0}.status = $1)
+                    SELECT COUNT(*) FROM {0} WHERE status = $1 AND cpus <= $2 AND mem <= $3{1}
+                    AND id NOT IN (SELECT id FROM stuck)",
+                    self.table_name,
+                    tags.sql(&self.table_name, |i| format!("${}", i + 4))
+                ),
+                &with_tags(
+                    vec![
+                        &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                        &i32::try_from(host.cpus).unwrap_or(i32::MAX),
+                        &i64::try_from(host.mem).unwrap_or(i64::MAX),
+                    ],
+                    tags,
+                ),
+            )
+            .await?;
+        Ok(row.get::<_, i64>(0) as usize)
+    }
+
+    async fn count_jobs_by_status(
+        &self,
+        tags: &TagFilter,
+    ) -> Result<Vec<(ExperimentStatus, usize)>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT status, COUNT(*) FROM {} WHERE 1 = 1{} GROUP BY status",
+                    self.table_name,
+                    tags.sql(&self.table_name, |i| format!("${}", i + 1))
+                ),
+                &with_tags(vec![], tags),
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                (
+                    ExperimentStatus::new(row.get::<_, i32>(0) as usize),
+                    row.get::<_, i64>(1) as usize,
+                )
+            })
+            .collect())
+    }
+
+    async fn record_run_stats(
+        &self,
+        id: usize,
+        claim_token: &str,
+        stats: RunStats,
+    ) -> Result<bool> {
+        let client = self.pool.get().await?;
+        let changed = client
+            .execute(
+                &format!(
+                    r"UPDATE {} SET exit_code = $1, term_signal = $2, start_time = $3,
+                    end_time = $4, wall_time = $5, user_time = $6, sys_time = $7, max_rss = $8,
+                    launch_error = $9
+                    WHERE id = $10 AND status = $11 AND claim_token = $12",
+                    self.table_name
+                ),
+                &[
+                    &stats.exit_code,
+                    &stats.signal,
+                    &stats.start_time,
+                    &stats.end_time,
+                    &stats.wall_time,
+                    &stats.user_time,
+                    &stats.sys_time,
+                    &stats.max_rss,
+                    &stats.launch_error,
+                    &(id as i32),
+                    &(ExperimentStatus::Running.to_db_code() as i32),
+                    &claim_token,
+                ],
+            )
+            .await?;
+        Ok(changed > 0)
+    }
+
+    async fn get_all_jobs(&self) -> Result<Vec<JobInfo>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT {} FROM {} ORDER BY id",
+                    JOB_INFO_COLUMNS, self.table_name
+                ),
+                &[],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| JobInfo {
+                id: row.get::<_, i32>(0) as usize,
+                command: row.get(1),
+                status: ExperimentStatus::new(row.get::<_, i32>(2) as usize),
+                attempts: row.get::<_, i32>(3) as usize,
+                run: RunStats {
+                    exit_code: row.get(4),
+                    signal: row.get(5),
+                    start_time: row.get(6),
+                    end_time: row.get(7),
+                    wall_time: row.get(8),
+                    user_time: row.get(9),
+                    sys_time: row.get(10),
+                    max_rss: row.get(11),
+                    launch_error: row.get(12),
+                },
+            })
+            .collect())
+    }
+
+    async fn get_param_values(&self, name: &str) -> Result<Vec<(usize, String)>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT job_id, value FROM {}_params WHERE name = $1 ORDER BY job_id",
+                    self.table_name
+                ),
+                &[&name],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<_, i32>(0) as usize, row.get(1)))
+            .collect())
+    }
+
+    async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()> {
+        let client = self.pool.get().await?;
+        client.batch_execute(WORKERS_TABLE).await?;
+        client
+            .execute(
+                &format!(
+                    r"INSERT INTO dpr_workers (worker_id, table_name, heartbeat, lease)
+                    VALUES ($1, $2, {0}, $3)
+                    ON CONFLICT (worker_id) DO UPDATE
+                    SET heartbeat = excluded.heartbeat, lease = excluded.lease",
+                    NOW
+                ),
+                &[
+                    &worker_id,
+                    &self.table_name.as_str(),
+                    &(lease.as_secs() as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn reclaim_lost_jobs(&self, max_attempts: Option<usize>) -> Result<Vec<usize>> {
+        let mut client = self.pool.get().await?;
+        client.batch_execute(WORKERS_TABLE).await?;
+        let tx = client.transaction().await?;
+        let lost = format!("table_name = $1 AND heartbeat + lease < {}", NOW);
+        let reclaimed = tx
+            .query(
+                &format!(
+                    r"UPDATE {0} SET lost_count = lost_count + 1,
+                    status = CASE WHEN attempts + 1 >= $4::INT THEN $5::INT ELSE $2::INT END,
+                    attempts = attempts + 1
+                    WHERE status = $3
+                    AND worker_id IN (SELECT worker_id FROM dpr_workers WHERE {1})
+                    RETURNING id",
+                    self.table_name, lost
+                ),
+                &[
+                    &self.table_name.as_str(),
+                    &(ExperimentStatus::NotRunning.to_db_code() as i32),
+                    &(ExperimentStatus::Running.to_db_code() as i32),
+                    &max_attempts.map(|max| max as i32),
+                    &(ExperimentStatus::FailedFinished.to_db_code() as i32),
+                ],
+            )
+            .await?;
+        tx.execute(
+            &format!("DELETE FROM dpr_workers WHERE {}", lost),
+            &[&self.table_name.as_str()],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(reclaimed
+            .iter()
+            .map(|row| row.get::<_, i32>(0) as usize)
+            .collect())
+    }
+
+    async fn remove_worker(&self, worker_id: &str) -> Result<()> {
+        let client = self.pool.get().await?;
+        client.batch_execute(WORKERS_TABLE).await?;
+        client
+            .execute(
+                "DELETE FROM dpr_workers WHERE worker_id = $1",
+                &[&worker_id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_setting(&self, name: &str) -> Result<Option<String>> {
+        let client = self.pool.get().await?;
+        client.batch_execute(SETTINGS_TABLE).await?;
+        let row = client
+            .query_opt(
+                "SELECT value FROM dpr_table_settings WHERE table_name = $1 AND name = $2",
+                &[&self.table_name.as_str(), &name],
+            )
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn set_setting(&self, name: &str, value: Option<String>) -> Result<()> {
+        let client = self.pool.get().await?;
+        client.batch_execute(SETTINGS_TABLE).await?;
+        match value {
+            Some(value) => {
+                client
+                    .execute(
+                        r"INSERT INTO dpr_table_settings (table_name, name, value)
+                        VALUES ($1, $2, $3)
+                        ON CONFLICT (table_name, name) DO UPDATE SET value = excluded.value",
+                        &[&self.table_name.as_str(), &name, &value],
+                    )
+                    .await?
+            }
+            None => {
+                client
+                    .execute(
+                        "DELETE FROM dpr_table_settings WHERE table_name = $1 AND name = $2",
+                        &[&self.table_name.as_str(), &name],
+                    )
+                    .await?
+            }
+        };
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.pool.close();
+        Ok(())
+    }
+}
@@ -1,21 +1,26 @@
-use super::{ExperimentDatabase, ExperimentStatus};
+use super::{ExperimentDatabase, ExperimentStatus, TagFilter};
 
 use anyhow::Result;
-use mysql_async::prelude::*;
+use std::collections::HashMap;
 
 impl ExperimentDatabase {
-    pub async fn print_stats(&self) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        let results: Vec<ExperimentStatus> = conn
-            .query_map(format!("SELECT status from {}", self.table_name), |x| {
-                ExperimentStatus::new(x)
-            })
-            .await?;
-        let name_vec = vec!["Available", "Running", "Success", "Failed", "Timeout"];
-        let mut result_vec = vec![0; 5];
-        for i in results {
-            // SAFETY: Database constraints ensures that status is always below 5.
-            result_vec[i.to_db_code()] += 1;
+    /// Prints how many jobs passing the tag filter have each status.
+    pub(crate) async fn print_stats(&self, tags: &TagFilter) -> Result<()> {
+        let counts = self.store.count_jobs_by_status(tags).await?;
+        let name_vec = [
+            "Available",
+            "Running",
+            "Success",
+            "Failed",
+            "Timeout",
+            "Killed",
+            "Launch error",
+            "Blocked",
+        ];
+        let mut result_vec = [0; 8];
+        for (status, count) in counts {
+            // SAFETY: Database constraints ensures that status is always below 8.
+            result_vec[status.to_db_code()] += count;
         }
         for i in 0..name_vec.len() {
             println!("{}: {}", name_vec[i], result_vec[i])
@@ -23,22 +28,147 @@ impl ExperimentDatabase {
         Ok(())
     }
 
-    pub async fn print_all_jobs(&self) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        let results: Vec<(String, ExperimentStatus)> = conn
-            .query_map(
-                format!("SELECT command, status from {}", self.table_name),
-                |(c, s)| (c, ExperimentStatus::new(s)),
-            )
-            .await?;
+    /// Prints, for each value of a template parameter, how many of the jobs
+    /// which got it have each status and the mean wall time of those which
+    /// succeeded. Values come in the order they were loaded, and jobs without
+    /// the parameter are counted under `-`.
+    pub async fn print_grouped_stats(&self, param: &str) -> Result<()> {
+        let results = self.store.get_all_jobs().await?;
+        let values: HashMap<usize, String> = self
+            .store
+            .get_param_values(param)
+            .await?
+            .into_iter()
+            .collect();
+        let mut groups: Vec<(&str, [usize; 8], Vec<f64>)> = vec![];
+        let mut group_of_value: HashMap<&str, usize> = HashMap::new();
+        for job in &results {
+            let value = values.get(&job.id).map_or("-", String::as_str);
+            let i = *group_of_value.entry(value).or_insert_with(|| {
+                groups.push((value, [0; 8], vec![]));
+                groups.len() - 1
+            });
+            let (_, counts, wall_times) = &mut groups[i];
+            counts[job.status.to_db_code()] += 1;
+            if job.status == ExperimentStatus::SuccessFinished {
+                wall_times.extend(job.run.wall_time);
+            }
+        }
+        if groups.is_empty() {
+            println!("Database is empty.")
+        } else {
+            println!(
+                "{}, Available, Running, Success, Failed, Timeout, Killed, Launch error, Blocked, Mean wall time (s)",
+                param
+            );
+            for (value, counts, wall_times) in groups {
+                let mean = (!wall_times.is_empty())
+                    .then(|| wall_times.iter().sum::<f64>() / wall_times.len() as f64);
+                let counts: Vec<String> = counts.iter().map(usize::to_string).collect();
+                println!(
+                    "{}, {}, {}",
+                    value,
+                    counts.join(", "),
+                    or_dash(mean.map(|t| format!("{:.3}", t)))
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the command and status of every job, and with `details` how
+    /// its last run ended and the resources it used.
+    pub async fn print_all_jobs(&self, details: bool) -> Result<()> {
+        let results = self.store.get_all_jobs().await?;
         if results.is_empty() {
             println!("Database is empty.")
+        } else if details {
+            println!("Id, Command, Status, Attempts, Exit code, Signal, Start, End, Wall time (s), User time (s), System time (s), Max RSS (KiB), Launch error");
+            for job in results {
+                let run = job.run;
+                println!(
+                    "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+                    job.id,
+                    job.command,
+                    job.status,
+                    job.attempts,
+                    or_dash(run.exit_code),
+                    or_dash(run.signal),
+                    or_dash(run.start_time.map(format_timestamp)),
+                    or_dash(run.end_time.map(format_timestamp)),
+                    or_dash(run.wall_time.map(|t| format!("{:.3}", t))),
+                    or_dash(run.user_time.map(|t| format!("{:.3}", t))),
+                    or_dash(run.sys_time.map(|t| format!("{:.3}", t))),
+                    or_dash(run.max_rss),
+                    or_dash(run.launch_error),
+                );
+            }
         } else {
-            println!("Command, Status");
-            for (cmd, status) in results {
-                println!("{}, {}", cmd, status);
+            println!("Command, Status, Attempts");
+            for job in results {
+                println!("{}, {}, {}", job.command, job.status, job.attempts);
             }
         }
         Ok(())
     }
+
+    /// Prints every job with the ids of the jobs it waits on, which did not
+    /// succeed yet.
+    pub async fn print_dependencies(&self) -> Result<()> {
+        let results = self.store.get_all_jobs().await?;
+        let mut waiting_on: HashMap<usize, Vec<String>> = HashMap::new();
+        for (job_id, depends_on) in self.store.get_waiting_on().await? {
+            waiting_on
+                .entry(job_id)
+                .or_default()
+                .push(depends_on.to_string());
+        }
+        if results.is_empty() {
+            println!("Database is empty.")
+        } else {
+            println!("Id, Command, Status, Waiting on");
+            for job in results {
+                println!(
+                    "{}, {}, {}, {}",
+                    job.id,
+                    job.command,
+                    job.status,
+                    waiting_on
+                        .get(&job.id)
+                        .map_or_else(|| "-".to_owned(), |ids| ids.join(" "))
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn or_dash(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "-".to_owned(), |v| v.to_string())
+}
+
+/// Formats a unix timestamp as a UTC date and time, such as
+/// `2024-01-27 14:05:00`.
+fn format_timestamp(timestamp: i64) -> String {
+    let (days, secs) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    // Civil date from days since 1970-01-01, after Howard Hinnant's
+    // `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
 }
@@ -0,0 +1,1000 @@
+use super::{
+    failed_status_codes, fill_content_hashes, legacy_schema_version, new_claim_token, Capacity,
+    Dependency, ExperimentStatus, Job, JobInfo, JobSelection, JobStore,
+    MigrationStep::{self, FillContentHashes, Sql},
+    RunStats, TableEntry, TagFilter, JOB_INFO_COLUMNS, SCHEMA_VERSION,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, ToSql, TransactionBehavior};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::task;
+
+/// How long a statement waits for another process holding the write lock
+/// before giving up with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SCHEMA_VERSIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_schema_versions (
+    table_name VARCHAR(64) NOT NULL PRIMARY KEY,
+    version INTEGER NOT NULL)";
+
+const SETTINGS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_table_settings (
+    table_name VARCHAR(64) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    value VARCHAR(500) NOT NULL,
+    PRIMARY KEY (table_name, name))";
+
+const WORKERS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS dpr_workers (
+    worker_id VARCHAR(64) NOT NULL PRIMARY KEY,
+    table_name VARCHAR(64) NOT NULL,
+    heartbeat INTEGER NOT NULL,
+    lease INTEGER NOT NULL)";
+
+/// Current unix time, in seconds, as seen by the database.
+const NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+
+/// Statements upgrading a table from schema version `i + 1` to `i + 2`.
+const MIGRATIONS: [&[MigrationStep]; SCHEMA_VERSION - 1] = [
+    // 2: row-level claims
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN claim_token CHAR(32)"),
+        Sql("CREATE INDEX {table}_status ON {table} (status)"),
+        Sql("CREATE INDEX {table}_claim_token ON {table} (claim_token)"),
+    ],
+    // 3: per-job timeouts, in seconds
+    &[Sql("ALTER TABLE {table} ADD COLUMN timeout INTEGER")],
+    // 4: timeout ladder tiers
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN timeout_count INTEGER NOT NULL DEFAULT 0",
+    )],
+    // 5: exit status, times and resource usage of the last run
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN exit_code INTEGER"),
+        Sql("ALTER TABLE {table} ADD COLUMN term_signal INTEGER"),
+        Sql("ALTER TABLE {table} ADD COLUMN start_time INTEGER"),
+        Sql("ALTER TABLE {table} ADD COLUMN end_time INTEGER"),
+        Sql("ALTER TABLE {table} ADD COLUMN wall_time REAL"),
+        Sql("ALTER TABLE {table} ADD COLUMN user_time REAL"),
+        Sql("ALTER TABLE {table} ADD COLUMN sys_time REAL"),
+        Sql("ALTER TABLE {table} ADD COLUMN max_rss INTEGER"),
+    ],
+    // 6: killed and launch error statuses. SQLite cannot change a constraint,
+    // so the table is rebuilt.
+    &[
+        Sql(r"CREATE TABLE {table}_v6 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command VARCHAR(500) NOT NULL,
+            status INTEGER NOT NULL,
+            claim_token CHAR(32),
+            timeout INTEGER,
+            timeout_count INTEGER NOT NULL DEFAULT 0,
+            exit_code INTEGER,
+            term_signal INTEGER,
+            start_time INTEGER,
+            end_time INTEGER,
+            wall_time REAL,
+            user_time REAL,
+            sys_time REAL,
+            max_rss INTEGER,
+            launch_error TEXT,
+            CHECK(status<7))"),
+        Sql(
+            r"INSERT INTO {table}_v6 (id, command, status, claim_token, timeout, timeout_count,
+            exit_code, term_signal, start_time, end_time, wall_time, user_time, sys_time, max_rss)
+        SELECT id, command, status, claim_token, timeout, timeout_count,
+            exit_code, term_signal, start_time, end_time, wall_time, user_time, sys_time, max_rss
+        FROM {table}",
+        ),
+        Sql("DROP TABLE {table}"),
+        Sql("ALTER TABLE {table}_v6 RENAME TO {table}"),
+        Sql("CREATE INDEX {table}_status ON {table} (status)"),
+        Sql("CREATE INDEX {table}_claim_token ON {table} (claim_token)"),
+    ],
+    // 7: workers holding the jobs, and jobs they lost
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN worker_id VARCHAR(64)"),
+        Sql("ALTER TABLE {table} ADD COLUMN lost_count INTEGER NOT NULL DEFAULT 0"),
+    ],
+    // 8: attempts, and when a retried job can be claimed again
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0"),
+        Sql("ALTER TABLE {table} ADD COLUMN retry_after INTEGER"),
+    ],
+    // 9: cores and memory, in MiB, needed by each job
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cpus INTEGER NOT NULL DEFAULT 1"),
+        Sql("ALTER TABLE {table} ADD COLUMN mem INTEGER NOT NULL DEFAULT 0"),
+    ],
+    // 10: priorities, the highest claimed first
+    &[Sql(
+        "ALTER TABLE {table} ADD COLUMN priority INTEGER NOT NULL DEFAULT 0",
+    )],
+    // 11: labels, dependencies and the blocked status. The table is rebuilt
+    // for the new constraint, as for version 6.
+    &[
+        Sql(r"CREATE TABLE {table}_v11 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            command VARCHAR(500) NOT NULL,
+            status INTEGER NOT NULL,
+            claim_token CHAR(32),
+            timeout INTEGER,
+            timeout_count INTEGER NOT NULL DEFAULT 0,
+            exit_code INTEGER,
+            term_signal INTEGER,
+            start_time INTEGER,
+            end_time INTEGER,
+            wall_time REAL,
+            user_time REAL,
+            sys_time REAL,
+            max_rss INTEGER,
+            launch_error TEXT,
+            worker_id VARCHAR(64),
+            lost_count INTEGER NOT NULL DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            retry_after INTEGER,
+            cpus INTEGER NOT NULL DEFAULT 1,
+            mem INTEGER NOT NULL DEFAULT 0,
+            priority INTEGER NOT NULL DEFAULT 0,
+            label VARCHAR(255),
+            CHECK(status<8))"),
+        Sql(
+            r"INSERT INTO {table}_v11 (id, command, status, claim_token, timeout, timeout_count, exit_code,
+            term_signal, start_time, end_time, wall_time, user_time, sys_time, max_rss, launch_error,
+            worker_id, lost_count, attempts, retry_after, cpus, mem, priority)
+        SELECT id, command, status, claim_token, timeout, timeout_count, exit_code,
+            term_signal, start_time, end_time, wall_time, user_time, sys_time, max_rss, launch_error,
+            worker_id, lost_count, attempts, retry_after, cpus, mem, priority
+        FROM {table}",
+        ),
+        Sql("DROP TABLE {table}"),
+        Sql("ALTER TABLE {table}_v11 RENAME TO {table}"),
+        Sql("CREATE INDEX {table}_status ON {table} (status)"),
+        Sql("CREATE INDEX {table}_claim_token ON {table} (claim_token)"),
+        Sql("CREATE INDEX {table}_label ON {table} (label)"),
+        Sql(r"CREATE TABLE {table}_deps (
+            job_id INTEGER NOT NULL,
+            depends_on INTEGER NOT NULL,
+            PRIMARY KEY (job_id, depends_on))"),
+        Sql("CREATE INDEX {table}_deps_depends_on ON {table}_deps (depends_on)"),
+    ],
+    // 12: tags
+    &[
+        Sql(r"CREATE TABLE {table}_tags (
+            job_id INTEGER NOT NULL,
+            tag VARCHAR(64) NOT NULL,
+            PRIMARY KEY (job_id, tag))"),
+        Sql("CREATE INDEX {table}_tags_tag ON {table}_tags (tag)"),
+    ],
+    // 13: values of the template parameters of each job
+    &[Sql(r"CREATE TABLE {table}_params (
+        job_id INTEGER NOT NULL,
+        name VARCHAR(64) NOT NULL,
+        value VARCHAR(500) NOT NULL,
+        PRIMARY KEY (job_id, name))")],
+    // 14: working directories and environment variables
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN cwd VARCHAR(500)"),
+        Sql(r"CREATE TABLE {table}_env (
+            job_id INTEGER NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (job_id, name))"),
+    ],
+    // 15: content hashes, unique so a job cannot be loaded twice
+    &[
+        Sql("ALTER TABLE {table} ADD COLUMN content_hash CHAR(64)"),
+        FillContentHashes,
+        Sql("CREATE UNIQUE INDEX {table}_content_hash ON {table} (content_hash)"),
+    ],
+];
+
+/// SQLite storage for the experiment tables. Several `dpr` processes on the
+/// same machine can share the same database file: the file is put in WAL mode
+/// so readers never block the writer, and every claim is an immediate
+/// transaction holding the write lock so a job can only be claimed once.
+#[derive(Clone, Debug)]
+pub(crate) struct SqliteDatabase {
+    conn: Arc<Mutex<Connection>>,
+    table_name: Arc<String>,
+}
+
+impl SqliteDatabase {
+    pub(crate) fn open(path: &Path, table_name: Arc<String>) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            table_name,
+        })
+    }
+
+    /// rusqlite is blocking, so every statement runs on tokio's blocking pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection, &str) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let table_name = self.table_name.clone();
+        let res = task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut conn, &table_name)
+        })
+        .await??;
+        Ok(res)
+    }
+}
+
+fn set_schema_version(conn: &Connection, table_name: &str, version: usize) -> rusqlite::Result<()> {
+    conn.execute_batch(SCHEMA_VERSIONS_TABLE)?;
+    conn.execute(
+        r"INSERT INTO dpr_schema_versions (table_name, version) VALUES (?1, ?2)
+        ON CONFLICT (table_name) DO UPDATE SET version = excluded.version",
+        params![table_name, version],
+    )?;
+    Ok(())
+}
+
+/// Applies the migration steps from version `from` up to `to`, each one in its
+/// own transaction.
+fn migrate_from(
+    conn: &mut Connection,
+    table_name: &str,
+    from: usize,
+    to: usize,
+) -> rusqlite::Result<()> {
+    for (i, step) in MIGRATIONS.iter().enumerate().take(to - 1).skip(from - 1) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for statement in step.iter() {
+            match statement {
+                Sql(sql) => tx.execute_batch(&sql.replace("{table}", table_name))?,
+                FillContentHashes => fill_table_content_hashes(&tx, table_name)?,
+                MigrationStep::DropStatusCheck => {
+                    unreachable!("SQLite tables are rebuilt to change a check")
+                }
+            }
+        }
+        set_schema_version(&tx, table_name, i + 2)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn fill_table_content_hashes(conn: &Connection, table_name: &str) -> rusqlite::Result<()> {
+    let jobs = conn
+        .prepare(&format!(
+            "SELECT id, command, cwd FROM {} ORDER BY id",
+            table_name
+        ))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let env = conn
+        .prepare(&format!(
+            "SELECT job_id, name, value FROM {}_env",
+            table_name
+        ))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut stmt = conn.prepare(&format!(
+        "UPDATE {} SET content_hash = ?1 WHERE id = ?2",
+        table_name
+    ))?;
+    for (content_hash, id) in fill_content_hashes(jobs, env) {
+        stmt.execute(params![content_hash, id])?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl JobStore for SqliteDatabase {
+    async fn create_table(&self, version: usize) -> Result<()> {
+        self.with_conn(move |conn, table_name| {
+            conn.execute_batch(&format!(
+                r"BEGIN IMMEDIATE;
+                DROP TABLE IF EXISTS {0};
+                DROP TABLE IF EXISTS {0}_deps;
+                DROP TABLE IF EXISTS {0}_tags;
+                DROP TABLE IF EXISTS {0}_params;
+                DROP TABLE IF EXISTS {0}_env;
+                CREATE TABLE {0} (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    command VARCHAR(500) NOT NULL,
+                    status INTEGER NOT NULL,
+                    CHECK(status<5));
+                COMMIT;",
+                table_name
+            ))?;
+            set_schema_version(conn, table_name, 1)?;
+            conn.execute_batch(SETTINGS_TABLE)?;
+            conn.execute(
+                "DELETE FROM dpr_table_settings WHERE table_name = ?1",
+                params![table_name],
+            )?;
+            migrate_from(conn, table_name, 1, version)
+        })
+        .await
+    }
+
+    async fn schema_version(&self) -> Result<usize> {
+        self.with_conn(|conn, table_name| {
+            conn.execute_batch(&format!("SELECT id FROM {} LIMIT 0", table_name))?;
+            conn.execute_batch(SCHEMA_VERSIONS_TABLE)?;
+            let version = conn
+                .query_row(
+                    "SELECT version FROM dpr_schema_versions WHERE table_name = ?1",
+                    params![table_name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(version.unwrap_or_else(|| {
+                let has_claim_token = conn
+                    .execute_batch(&format!("SELECT claim_token FROM {} LIMIT 0", table_name))
+                    .is_ok();
+                legacy_schema_version(has_claim_token)
+            }))
+        })
+        .await
+    }
+
+    async fn migrate(&self, from: usize) -> Result<()> {
+        self.with_conn(move |conn, table_name| migrate_from(conn, table_name, from, SCHEMA_VERSION))
+            .await
+    }
+
+    async fn insert_commands(&self, commands: Vec<String>) -> Result<()> {
+        self.with_conn(move |conn, table_name| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "INSERT INTO {} (command, status) VALUES (?1, ?2)",
+                    table_name
+                ))?;
+                for command in commands {
+                    stmt.execute(params![command, ExperimentStatus::NotRunning.to_db_code()])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn insert_jobs(&self, table_entries: Vec<TableEntry>) -> Result<Vec<usize>> {
+        self.with_conn(move |conn, table_name| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut ids = Vec::with_capacity(table_entries.len());
+            {
+                let mut stmt = tx.prepare(&format!(
+                    r"INSERT INTO {} (command, status, timeout, cpus, mem, priority, label, cwd,
+                    content_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    table_name
+                ))?;
+                let mut tag_stmt = tx.prepare(&format!(
+                    "INSERT INTO {}_tags (job_id, tag) VALUES (?1, ?2)",
+                    table_name
+                ))?;
+                let mut param_stmt = tx.prepare(&format!(
+                    "INSERT INTO {}_params (job_id, name, value) VALUES (?1, ?2, ?3)",
+                    table_name
+                ))?;
+                let mut env_stmt = tx.prepare(&format!(
+                    "INSERT INTO {}_env (job_id, name, value) VALUES (?1, ?2, ?3)",
+                    table_name
+                ))?;
+                for t in table_entries {
+                    let content_hash = t.content_hash();
+                    stmt.execute(params![
+                        t.command,
+                        t.status.to_db_code(),
+                        t.timeout.map(|d| d.as_secs()),
+                        t.cpus,
+                        t.mem,
+                        t.priority,
+                        t.label,
+                        t.cwd,
+                        content_hash
+                    ])?;
+                    let id = tx.last_insert_rowid();
+                    for tag in &t.tags {
+                        tag_stmt.execute(params![id, tag])?;
+                    }
+                    for (name, value) in &t.params {
+                        param_stmt.execute(params![id, name, value])?;
+                    }
+                    for (name, value) in &t.env {
+                        env_stmt.execute(params![id, name, value])?;
+                    }
+                    ids.push(id as usize);
+                }
+            }
+            tx.commit()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn get_content_hashes(&self) -> Result<Vec<(String, usize, ExperimentStatus)>> {
+        self.with_conn(|conn, table_name| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT content_hash, id, status FROM {} WHERE content_hash IS NOT NULL",
+                table_name
+            ))?;
+            let hashes = stmt
+                .query_map([], |row| {
+                    Ok((row.get(0)?, row.get(1)?, ExperimentStatus::new(row.get(2)?)))
+                })?
+                .collect();
+            hashes
+        })
+        .await
+    }
+
+    async fn find_jobs(&self, dependency: &Dependency) -> Result<Vec<usize>> {
+        let dependency = dependency.clone();
+        self.with_conn(move |conn, table_name| {
+            let (column, value) = match dependency {
+                Dependency::Id(id) => ("id", id.to_string()),
+                Dependency::Label(label) => ("label", label),
+            };
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id FROM {} WHERE {} = ?1 ORDER BY id",
+                table_name, column
+            ))?;
+            let ids = stmt.query_map(params![value], |row| row.get(0))?.collect();
+            ids
+        })
+        .await
+    }
+
+    async fn insert_dependencies(&self, dependencies: Vec<(usize, usize)>) -> Result<()> {
+        self.with_conn(move |conn, table_name| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "INSERT INTO {}_deps (job_id, depends_on) VALUES (?1, ?2)",
+                    table_name
+                ))?;
+                for (job_id, depends_on) in dependencies {
+                    stmt.execute(params![job_id, depends_on])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        self.with_conn(move |conn, table_name| {
+            let failed_dependency = format!(
+                r"EXISTS (SELECT 1 FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                WHERE d.job_id = {0}.id AND p.status IN ({1}))",
+                table_name,
+                failed_status_codes()
+            );
+            let available = ExperimentStatus::NotRunning.to_db_code();
+            let blocked_status = ExperimentStatus::Blocked.to_db_code();
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut blocked = 0;
+            {
+                let mut unblock = tx.prepare(&format!(
+                    "UPDATE {} SET status = ?1 WHERE id = ?3 AND status = ?2 AND NOT {}",
+                    table_name, failed_dependency
+                ))?;
+                let mut block = tx.prepare(&format!(
+                    "UPDATE {} SET status = ?2 WHERE id = ?3 AND status = ?1 AND {}",
+                    table_name, failed_dependency
+                ))?;
+                let mut dependants = tx.prepare(&format!(
+                    "SELECT job_id FROM {}_deps WHERE depends_on = ?1",
+                    table_name
+                ))?;
+                // The given jobs are checked and all their dependants after
+                // them, then only the dependants of the jobs which changed.
+                let mut level = ids;
+                let mut changed = level.clone();
+                while !level.is_empty() {
+                    for id in level.drain(..) {
+                        let codes = params![available, blocked_status, id];
+                        let newly_blocked = block.execute(codes)?;
+                        blocked += newly_blocked;
+                        if newly_blocked + unblock.execute(codes)? > 0 {
+                            changed.push(id);
+                        }
+                    }
+                    for id in changed.drain(..) {
+                        level.extend(
+                            dependants
+                                .query_map(params![id], |row| row.get::<_, usize>(0))?
+                                .collect::<rusqlite::Result<Vec<_>>>()?,
+                        );
+                    }
+                    level.sort_unstable();
+                    level.dedup();
+                }
+            }
+            tx.commit()?;
+            Ok(blocked)
+        })
+        .await
+    }
+
+    async fn get_waiting_on(&self) -> Result<Vec<(usize, usize)>> {
+        self.with_conn(|conn, table_name| {
+            let mut stmt = conn.prepare(&format!(
+                r"SELECT d.job_id, d.depends_on FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                WHERE p.status <> ?1 ORDER BY d.job_id, d.depends_on",
+                table_name
+            ))?;
+            let pairs = stmt
+                .query_map(
+                    params![ExperimentStatus::SuccessFinished.to_db_code()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?
+                .collect();
+            pairs
+        })
+        .await
+    }
+
+    async fn claim_jobs(
+        &self,
+        mut capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>> {
+        let order = if shuffle {
+            "ORDER BY priority DESC, RANDOM()"
+        } else {
+            "ORDER BY priority DESC, id"
+        };
+        let claim_token = Arc::new(new_claim_token());
+        let worker_id = worker_id.to_owned();
+        let tags = tags.clone();
+        self.with_conn(move |conn, table_name| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut jobs = {
+                let status = ExperimentStatus::NotRunning.to_db_code();
+                let success = ExperimentStatus::SuccessFinished.to_db_code();
+                let mut values: Vec<&dyn ToSql> =
+                    vec![&status, &capacity.cpus, &capacity.mem, &success];
+                values.extend(tags.values().map(|tag| tag as &dyn ToSql));
+                let mut stmt = tx.prepare(&format!(
+                    r"SELECT id, command, timeout, timeout_count, attempts, cpus, mem, cwd FROM {0}
+                    WHERE status = ?1 AND (retry_after IS NULL OR retry_after <= {1})
+                    AND cpus <= ?2 AND mem <= ?3
+                    AND NOT EXISTS (SELECT 1 FROM {0}_deps d JOIN {0} p ON p.id = d.depends_on
+                    WHERE d.job_id = {0}.id AND p.status <> ?4){3}
+                    {2}",
+                    table_name,
+                    NOW,
+                    order,
+                    tags.sql(table_name, |i| format!("?{}", i + 5))
+                ))?;
+                let mut candidates = stmt.query_map(&*values, |row| {
+                    Ok(Job {
+                        id: row.get(0)?,
+                        command: Arc::new(row.get(1)?),
+                        timeout: row.get::<_, Option<u64>>(2)?.map(Duration::from_secs),
+                        timeout_count: row.get(3)?,
+                        attempts: row.get(4)?,
+                        cpus: row.get(5)?,
+                        mem: row.get(6)?,
+                        cwd: row.get::<_, Option<String>>(7)?.map(PathBuf::from),
+                        env: vec![],
+                        claim_token: claim_token.clone(),
+                    })
+                })?;
+                // Candidates are read until the capacity runs out, so smaller
+                // jobs after one which does not fit still get the room left.
+                let mut jobs = vec![];
+                while capacity.jobs > 0 {
+                    match candidates.next() {
+                        Some(job) => {
+                            let job = job?;
+                            if capacity.take(&job) {
+                                jobs.push(job);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                jobs
+            };
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "SELECT name, value FROM {}_env WHERE job_id = ?1 ORDER BY name",
+                    table_name
+                ))?;
+                for job in &mut jobs {
+                    job.env = stmt
+                        .query_map(params![job.id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                        .collect::<rusqlite::Result<_>>()?;
+                }
+            }
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "UPDATE {} SET status = ?1, claim_token = ?2, worker_id = ?3 WHERE id = ?4",
+                    table_name
+                ))?;
+                for job in &jobs {
+                    stmt.execute(params![
+                        ExperimentStatus::Running.to_db_code(),
+                        job.claim_token,
+                        worker_id,
+                        job.id
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(jobs)
+        })
+        .await
+    }
+
+    async fn requeue_jobs(&self, ids: Vec<usize>) -> Result<usize> {
+        self.with_conn(move |conn, table_name| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut requeued = 0;
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "UPDATE {} SET status = ?1 WHERE id = ?2 AND status <> ?3",
+                    table_name
+                ))?;
+                for id in ids {
+                    requeued += stmt.execute(params![
+                        ExperimentStatus::NotRunning.to_db_code(),
+                        id,
+                        ExperimentStatus::Running.to_db_code()
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(requeued)
+        })
+        .await
+    }
+
+    async fn release_job(&self, id: usize, claim_token: &str) -> Result<bool> {
+        let claim_token = claim_token.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET status = ?1 WHERE id = ?2 AND status = ?3 AND claim_token = ?4",
+                    table_name
+                ),
+                params![
+                    ExperimentStatus::NotRunning.to_db_code(),
+                    id,
+                    ExperimentStatus::Running.to_db_code(),
+                    claim_token
+                ],
+            )
+            .map(|changed| changed > 0)
+        })
+        .await
+    }
+
+    async fn record_attempt(
+        &self,
+        id: usize,
+        claim_token: &str,
+        new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
+    ) -> Result<bool> {
+        let claim_token = claim_token.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute(
+                &format!(
+                    r"UPDATE {} SET status = ?1, attempts = attempts + 1,
+                    timeout_count = timeout_count + ?2, retry_after = ?3 + {}
+                    WHERE id = ?4 AND status = ?5 AND claim_token = ?6",
+                    table_name, NOW
+                ),
+                params![
+                    new_status.to_db_code(),
+                    timed_out as usize,
+                    retry_delay.map(|d| d.as_secs()),
+                    id,
+                    ExperimentStatus::Running.to_db_code(),
+                    claim_token
+                ],
+            )
+            .map(|changed| changed > 0)
+        })
+        .await
+    }
+
+    async fn reset_jobs(
+        &self,
+        status: Option<ExperimentStatus>,
+        tags: &TagFilter,
+    ) -> Result<Vec<usize>> {
+        let tags = tags.clone();
+        self.with_conn(move |conn, table_name| {
+            let available = ExperimentStatus::NotRunning.to_db_code();
+            let status = status.map(|s| s.to_db_code());
+            let mut values: Vec<&dyn ToSql> = vec![&available, &status];
+            values.extend(tags.values().map(|tag| tag as &dyn ToSql));
+            let mut stmt = conn.prepare(&format!(
+                r"UPDATE {} SET status = ?1, attempts = 0, timeout_count = 0, retry_after = NULL
+                WHERE (?2 IS NULL OR status = ?2){} RETURNING id",
+                table_name,
+                tags.sql(table_name, |i| format!("?{}", i + 3))
+            ))?;
+            let ids = stmt.query_map(&*values, |row| row.get(0))?.collect();
+            ids
+        })
+        .await
+    }
+
+    async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<usize> {
+        self.with_conn(move |conn, table_name| match jobs {
+            JobSelection::Ids(ids) => {
+                let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                let mut changed = 0;
+                {
+                    let mut stmt = tx.prepare(&format!(
+                        "UPDATE {} SET priority = ?1 WHERE id = ?2",
+                        table_name
+                    ))?;
+                    for id in ids {
+                        changed += stmt.execute(params![priority, id])?;
+                    }
+                }
+                tx.commit()?;
+                Ok(changed)
+            }
+            JobSelection::Matching(text) => conn.execute(
+                &format!(
+                    "UPDATE {} SET priority = ?1 WHERE instr(command, ?2) > 0",
+                    table_name
+                ),
+                params![priority, text],
+            ),
+        })
+        .await
+    }
+
+    async fn count_fitting_jobs(&self, host: Capacity, tags: &TagFilter) -> Result<usize> {
+        let tags = tags.clone();
+        self.with_conn(move |conn, table_name| {
+            let status = ExperimentStatus::NotRunning.to_db_code();
+            let mut values: Vec<&dyn ToSql> = vec![&status, &host.cpus, &host.mem];
+            values.extend(tags.values().map(|tag| tag as &dyn ToSql));
+            conn.query_row(
+                &format!(
+                    r"WITH RECURSIVE stuck(id) AS (
+                    SELECT id FROM {0} WHERE status = ?1 AND NOT (cpus <= ?2 AND mem <= ?3{1})
+                    UNION
+                    SELECT d.job_id FROM {0}_deps d JOIN stuck s ON s.id = d.depends_on
+                    JOIN {0} ON {0}.id = d.job_id WHERE {0}.status = ?1)
+                    SELECT COUNT(*) FROM {0} WHERE status = ?1 AND cpus <= ?2 AND mem <= ?3{1}
+                    AND id NOT IN (SELECT id FROM stuck)",
+                    table_name,
+                    tags.sql(table_name, |i| format!("?{}", i + 4))
+                ),
+                &*values,
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    async fn count_jobs_by_status(
+        &self,
+        tags: &TagFilter,
+    ) -> Result<Vec<(ExperimentStatus, usize)>> {
+        let tags = tags.clone();
+        self.with_conn(move |conn, table_name| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT status, COUNT(*) FROM {} WHERE 1 = 1{} GROUP BY status",
+                table_name,
+                tags.sql(table_name, |i| format!("?{}", i + 1))
+            ))?;
+            let counts = stmt
+                .query_map(rusqlite::params_from_iter(tags.values()), |row| {
+                    Ok((ExperimentStatus::new(row.get(0)?), row.get(1)?))
+                })?
+                .collect();
+            counts
+        })
+        .await
+    }
+
+    async fn record_run_stats(
+        &self,
+        id: usize,
+        claim_token: &str,
+        stats: RunStats,
+    ) -> Result<bool> {
+        let claim_token = claim_token.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute(
+                &format!(
+                    r"UPDATE {} SET exit_code = ?1, term_signal = ?2, start_time = ?3,
+                    end_time = ?4, wall_time = ?5, user_time = ?6, sys_time = ?7, max_rss = ?8,
+                    launch_error = ?9
+                    WHERE id = ?10 AND status = ?11 AND claim_token = ?12",
+                    table_name
+                ),
+                params![
+                    stats.exit_code,
+                    stats.signal,
+                    stats.start_time,
+                    stats.end_time,
+                    stats.wall_time,
+                    stats.user_time,
+                    stats.sys_time,
+                    stats.max_rss,
+                    stats.launch_error,
+                    id,
+                    ExperimentStatus::Running.to_db_code(),
+                    claim_token
+                ],
+            )
+            .map(|changed| changed > 0)
+        })
+        .await
+    }
+
+    async fn get_all_jobs(&self) -> Result<Vec<JobInfo>> {
+        self.with_conn(|conn, table_name| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM {} ORDER BY id",
+                JOB_INFO_COLUMNS, table_name
+            ))?;
+            let jobs = stmt
+                .query_map([], |row| {
+                    Ok(JobInfo {
+                        id: row.get(0)?,
+                        command: row.get(1)?,
+                        status: ExperimentStatus::new(row.get(2)?),
+                        attempts: row.get(3)?,
+                        run: RunStats {
+                            exit_code: row.get(4)?,
+                            signal: row.get(5)?,
+                            start_time: row.get(6)?,
+                            end_time: row.get(7)?,
+                            wall_time: row.get(8)?,
+                            user_time: row.get(9)?,
+                            sys_time: row.get(10)?,
+                            max_rss: row.get(11)?,
+                            launch_error: row.get(12)?,
+                        },
+                    })
+                })?
+                .collect();
+            jobs
+        })
+        .await
+    }
+
+    async fn get_param_values(&self, name: &str) -> Result<Vec<(usize, String)>> {
+        let name = name.to_owned();
+        self.with_conn(move |conn, table_name| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT job_id, value FROM {}_params WHERE name = ?1 ORDER BY job_id",
+                table_name
+            ))?;
+            let values = stmt
+                .query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect();
+            values
+        })
+        .await
+    }
+
+    async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()> {
+        let worker_id = worker_id.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute_batch(WORKERS_TABLE)?;
+            conn.execute(
+                &format!(
+                    r"INSERT INTO dpr_workers (worker_id, table_name, heartbeat, lease)
+                    VALUES (?1, ?2, {0}, ?3)
+                    ON CONFLICT (worker_id) DO UPDATE
+                    SET heartbeat = excluded.heartbeat, lease = excluded.lease",
+                    NOW
+                ),
+                params![worker_id, table_name, lease.as_secs()],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn reclaim_lost_jobs(&self, max_attempts: Option<usize>) -> Result<Vec<usize>> {
+        self.with_conn(move |conn, table_name| {
+            conn.execute_batch(WORKERS_TABLE)?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let lost = format!("table_name = ?1 AND heartbeat + lease < {}", NOW);
+            let reclaimed = tx
+                .prepare(&format!(
+                    r"UPDATE {0} SET lost_count = lost_count + 1,
+                    status = CASE WHEN attempts + 1 >= ?4 THEN ?5 ELSE ?2 END,
+                    attempts = attempts + 1
+                    WHERE status = ?3
+                    AND worker_id IN (SELECT worker_id FROM dpr_workers WHERE {1})
+                    RETURNING id",
+                    table_name, lost
+                ))?
+                .query_map(
+                    params![
+                        table_name,
+                        ExperimentStatus::NotRunning.to_db_code(),
+                        ExperimentStatus::Running.to_db_code(),
+                        max_attempts,
+                        ExperimentStatus::FailedFinished.to_db_code()
+                    ],
+                    |row| row.get(0),
+                )?
+                .collect::<rusqlite::Result<Vec<usize>>>()?;
+            tx.execute(
+                &format!("DELETE FROM dpr_workers WHERE {}", lost),
+                params![table_name],
+            )?;
+            tx.commit()?;
+            Ok(reclaimed)
+        })
+        .await
+    }
+
+    async fn remove_worker(&self, worker_id: &str) -> Result<()> {
+        let worker_id = worker_id.to_owned();
+        self.with_conn(move |conn, _| {
+            conn.execute_batch(WORKERS_TABLE)?;
+            conn.execute(
+                "DELETE FROM dpr_workers WHERE worker_id = ?1",
+                params![worker_id],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_setting(&self, name: &str) -> Result<Option<String>> {
+        let name = name.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute_batch(SETTINGS_TABLE)?;
+            conn.query_row(
+                "SELECT value FROM dpr_table_settings WHERE table_name = ?1 AND name = ?2",
+                params![table_name, name],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn set_setting(&self, name: &str, value: Option<String>) -> Result<()> {
+        let name = name.to_owned();
+        self.with_conn(move |conn, table_name| {
+            conn.execute_batch(SETTINGS_TABLE)?;
+            match value {
+                Some(value) => conn.execute(
+                    r"INSERT INTO dpr_table_settings (table_name, name, value) VALUES (?1, ?2, ?3)
+                    ON CONFLICT (table_name, name) DO UPDATE SET value = excluded.value",
+                    params![table_name, name, value],
+                ),
+                None => conn.execute(
+                    "DELETE FROM dpr_table_settings WHERE table_name = ?1 AND name = ?2",
+                    params![table_name, name],
+                ),
+            }
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        Ok(())
+    }
+}
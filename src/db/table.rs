@@ -1,198 +1,843 @@
-use super::{ExperimentDatabase, ExperimentStatus, Job};
+use crate::{
+    duration::parse_duration,
+    size::parse_size,
+    template::{self, Param},
+};
+
+use super::{
+    jobfile::{self, JobFormat, Record},
+    Capacity, DatabaseError, Dependency, ExperimentDatabase, ExperimentStatus, Job, JobSelection,
+    LostLease, OnDuplicate, RetryPolicy, RunStats, TableEntry, TagFilter, FAILED_STATUSES,
+    SCHEMA_VERSION,
+};
 
 use anyhow::Result;
-use mysql_async::{prelude::*, Conn};
 use rand::{seq::SliceRandom, thread_rng};
-use std::{path::Path, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    mem,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// Table setting making runners execute commands through `sh -c`.
+const SHELL_SETTING: &str = "shell";
+/// Table setting listing the time limits, in seconds, of the successive runs
+/// of a job which keeps timing out.
+const TIMEOUT_LADDER_SETTING: &str = "timeout_ladder";
+/// Table settings of the retry policy: the most runs a job gets, the statuses
+/// which are retried and the seconds to wait before a retry.
+const MAX_ATTEMPTS_SETTING: &str = "max_attempts";
+const RETRY_ON_SETTING: &str = "retry_on";
+const RETRY_BACKOFF_SETTING: &str = "retry_backoff";
+
+/// Statuses which can be retried, by the names of their `edit --reset-*`
+/// flags.
+const RETRIABLE_STATUSES: [(&str, ExperimentStatus); 4] = [
+    ("failed", ExperimentStatus::FailedFinished),
+    ("timeout", ExperimentStatus::TimedOut),
+    ("killed", ExperimentStatus::Killed),
+    ("launch-error", ExperimentStatus::LaunchError),
+];
+
+/// Keys of the directive lines of plain text commands files, which are also
+/// the attribute fields of structured job files.
+const DIRECTIVES: [&str; 7] = [
+    "timeout", "cpus", "mem", "priority", "label", "after", "tags",
+];
+
+/// Jobs of a load which were already loaded.
+#[derive(Default)]
+struct Duplicates {
+    skipped: usize,
+    /// Ids of the jobs to make available again.
+    requeue: Vec<usize>,
+}
 
-struct TableEntry<'a> {
-    command: &'a str,
-    status: ExperimentStatus,
+/// Why a job was made available again after an attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Requeue {
+    /// To run with the next time limit of the timeout ladder.
+    NextTimeout,
+    /// To run again under the retry policy.
+    Retry,
 }
 
 impl ExperimentDatabase {
     pub async fn create_table(&self) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        conn.query_drop(format!(
-            r"CREATE OR REPLACE TABLE {} (
-                    id int NOT NULL AUTO_INCREMENT, 
-                    command VARCHAR(500) NOT NULL, 
-                    status int NOT NULL, 
-                    CHECK(status<5),
-                    PRIMARY KEY (id))",
-            self.table_name
-        ))
-        .await?;
+        self.store.create_table(SCHEMA_VERSION).await
+    }
+
+    /// Creates the table with an older schema version, holding the commands
+    /// of a plain text commands file without their directives, to test
+    /// migrations on.
+    pub async fn create_legacy_table(
+        &self,
+        version: usize,
+        commands_file: Option<&Path>,
+    ) -> Result<()> {
+        if !(1..=SCHEMA_VERSION).contains(&version) {
+            return Err(DatabaseError::InvalidSchemaVersion(version).into());
+        }
+        self.store.create_table(version).await?;
+        if let Some(commands_file) = commands_file {
+            let file_contents = String::from_utf8(tokio::fs::read(commands_file).await?)?;
+            let commands = parse_commands(&file_contents)?
+                .into_iter()
+                .map(|t| t.command)
+                .collect();
+            self.store.insert_commands(commands).await?;
+        }
         Ok(())
     }
-    pub async fn load_commands(&self, commands_file: &Path, shuffle: bool) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
+
+    /// Fails unless the table has exactly the schema version of this build.
+    pub async fn check_schema_version(&self) -> Result<()> {
+        let version = self.store.schema_version().await?;
+        match version.cmp(&SCHEMA_VERSION) {
+            Ordering::Less => Err(DatabaseError::OutdatedSchema(version).into()),
+            Ordering::Greater => Err(DatabaseError::UnknownSchema(version).into()),
+            Ordering::Equal => Ok(()),
+        }
+    }
+
+    pub async fn migrate(&self) -> Result<()> {
+        let version = self.store.schema_version().await?;
+        match version.cmp(&SCHEMA_VERSION) {
+            Ordering::Less => {
+                self.store.migrate(version).await?;
+                println!(
+                    "Migrated table schema from version {} to {}",
+                    version, SCHEMA_VERSION
+                );
+            }
+            Ordering::Greater => return Err(DatabaseError::UnknownSchema(version).into()),
+            Ordering::Equal => println!("Table schema is already at version {}", version),
+        }
+        Ok(())
+    }
+
+    /// Loads the jobs of a commands file, in the given format or the one of
+    /// its extension.
+    ///
+    /// Plain text files hold one command per line. Directive lines such as
+    /// `#timeout: 1h` set an attribute of every command after them, until the
+    /// next directive with the same key. An empty value clears it. Records of
+    /// structured files give their attributes with the same keys and values,
+    /// along with a working directory and environment variables.
+    ///
+    /// `after` lists the ids and labels of the jobs the commands depend on.
+    /// Labels are looked up among the jobs already in the table and those of
+    /// the file, and every reference is checked before any job is inserted.
+    pub async fn load_commands(
+        &self,
+        commands_file: &Path,
+        format: Option<JobFormat>,
+        on_duplicate: OnDuplicate,
+        shuffle: bool,
+    ) -> Result<()> {
         let file_contents = String::from_utf8(tokio::fs::read(commands_file).await?)?;
-        let mut table_entries = vec![];
-        for line in file_contents.lines() {
-            let t = TableEntry {
-                command: line.trim_end(),
-                status: ExperimentStatus::NotRunning,
+        let mut table_entries = match format.unwrap_or_else(|| JobFormat::from_path(commands_file))
+        {
+            JobFormat::Text => parse_commands(&file_contents)?,
+            format => jobfile::parse_records(&file_contents, format)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, record)| record_entry(i + 1, record))
+                .collect::<Result<_>>()?,
+        };
+        if shuffle {
+            let mut rng = thread_rng();
+            table_entries.shuffle(&mut rng);
+        }
+        let duplicates = self
+            .remove_duplicates(&mut table_entries, on_duplicate)
+            .await?;
+        let afters: Vec<Vec<Dependency>> = table_entries
+            .iter_mut()
+            .map(|t| mem::take(&mut t.after))
+            .collect();
+        // Jobs which the new ones depend on: the ids of those already in the
+        // table, and the positions of those in the file.
+        let mut targets: HashMap<&Dependency, (Vec<usize>, Vec<usize>)> = HashMap::new();
+        for dependency in afters.iter().flatten() {
+            if targets.contains_key(dependency) {
+                continue;
+            }
+            let ids = self.store.find_jobs(dependency).await?;
+            let in_file: Vec<usize> = match dependency {
+                Dependency::Label(label) => (0..table_entries.len())
+                    .filter(|&i| table_entries[i].label.as_ref() == Some(label))
+                    .collect(),
+                Dependency::Id(_) => vec![],
             };
-            table_entries.push(t);
+            if ids.is_empty() && in_file.is_empty() {
+                return Err(DatabaseError::UnknownDependency(dependency.to_string()).into());
+            }
+            targets.insert(dependency, (ids, in_file));
+        }
+        let mut in_table = vec![];
+        let mut in_file = vec![];
+        for (i, after) in afters.iter().enumerate() {
+            for dependency in after {
+                let (ids, positions) = &targets[dependency];
+                in_table.extend(ids.iter().map(|&id| (i, id)));
+                in_file.extend(positions.iter().filter(|&&j| j != i).map(|&j| (i, j)));
+            }
         }
+        check_acyclic(table_entries.len(), &in_file)?;
+        let ids = self.store.insert_jobs(table_entries).await?;
+        let mut dependencies: Vec<(usize, usize)> = in_table
+            .into_iter()
+            .map(|(i, id)| (ids[i], id))
+            .chain(in_file.into_iter().map(|(i, j)| (ids[i], ids[j])))
+            .collect();
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        let mut dependants: Vec<usize> = dependencies.iter().map(|&(id, _)| id).collect();
+        dependants.dedup();
+        self.store.insert_dependencies(dependencies).await?;
+        self.update_blocked_jobs(dependants).await?;
+        self.requeue_duplicates(ids.len(), duplicates).await
+    }
+
+    /// Loads one job per combination of the values of the template
+    /// parameters, recording the values each job got.
+    pub(crate) async fn load_template(
+        &self,
+        command_template: &str,
+        params: &[Param],
+        zip: bool,
+        on_duplicate: OnDuplicate,
+        shuffle: bool,
+    ) -> Result<()> {
+        let mut table_entries: Vec<TableEntry> = template::expand(command_template, params, zip)?
+            .into_iter()
+            .map(|(command, params)| TableEntry {
+                params,
+                ..JobAttributes::default().entry(command)
+            })
+            .collect();
         if shuffle {
             let mut rng = thread_rng();
             table_entries.shuffle(&mut rng);
         }
-        let params = table_entries.into_iter().map(|t| {
-            params! {
-                "command" => t.command,
-                "status" => t.status.to_db_code(),
+        let duplicates = self
+            .remove_duplicates(&mut table_entries, on_duplicate)
+            .await?;
+        let ids = self.store.insert_jobs(table_entries).await?;
+        self.requeue_duplicates(ids.len(), duplicates).await
+    }
+
+    /// Numbers the copies of the jobs repeated in the list, and removes the
+    /// jobs already in the table unless duplicates are an error.
+    async fn remove_duplicates(
+        &self,
+        table_entries: &mut Vec<TableEntry>,
+        on_duplicate: OnDuplicate,
+    ) -> Result<Duplicates> {
+        let loaded: HashMap<String, (usize, ExperimentStatus)> = self
+            .store
+            .get_content_hashes()
+            .await?
+            .into_iter()
+            .map(|(content_hash, id, status)| (content_hash, (id, status)))
+            .collect();
+        let mut copies: HashMap<String, usize> = HashMap::new();
+        let mut duplicates = Duplicates::default();
+        for mut t in mem::take(table_entries) {
+            let copy = copies.entry(t.content_hash()).or_default();
+            t.copy = *copy;
+            *copy += 1;
+            let (id, status) = match loaded.get(&t.content_hash()) {
+                Some(&existing) => existing,
+                None => {
+                    table_entries.push(t);
+                    continue;
+                }
+            };
+            match on_duplicate {
+                OnDuplicate::Error => {
+                    return Err(DatabaseError::DuplicateJob(t.command).into());
+                }
+                OnDuplicate::Requeue
+                    if !matches!(
+                        status,
+                        ExperimentStatus::NotRunning | ExperimentStatus::Running
+                    ) =>
+                {
+                    duplicates.requeue.push(id)
+                }
+                _ => duplicates.skipped += 1,
             }
-        });
-        conn.exec_batch(
-            format!(
-                r"INSERT INTO {}
-                            (command,status) values (:command, :status)",
-                self.table_name
-            ),
-            params,
-        )
-        .await?;
-        Ok(())
+        }
+        Ok(duplicates)
     }
 
-    pub async fn reset_all_jobs(&self) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        let ids: Vec<usize> = conn
-            .query(format!("SELECT id from {}", self.table_name))
-            .await?;
-        self.reset_given_ids(ids).await?;
+    /// Makes the duplicates to requeue available again, once the other jobs
+    /// are loaded, and prints what happened to the jobs of the load.
+    async fn requeue_duplicates(&self, inserted: usize, duplicates: Duplicates) -> Result<()> {
+        let requeued = if duplicates.requeue.is_empty() {
+            0
+        } else {
+            let requeued = self.store.requeue_jobs(duplicates.requeue.clone()).await?;
+            self.update_blocked_jobs(duplicates.requeue).await?;
+            requeued
+        };
+        println!(
+            "Inserted {} job(s), skipped {} and requeued {} duplicate(s)",
+            inserted, duplicates.skipped, requeued
+        );
         Ok(())
     }
 
-    pub(crate) async fn reset_jobs_with_status(&self, status: ExperimentStatus) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        let ids: Vec<usize> = conn
-            .exec(
-                format!("SELECT id from {} WHERE status = :status", self.table_name),
-                params! {
-                    "status" => status.to_db_code(),
-                },
-            )
-            .await?;
-        self.reset_given_ids(ids).await?;
+    /// Blocks the given jobs and their dependants which can no longer run as
+    /// a dependency failed, and unblocks those whose dependencies were reset
+    /// since.
+    async fn update_blocked_jobs(&self, ids: Vec<usize>) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let blocked = self.store.update_blocked_jobs(ids).await?;
+        if blocked > 0 {
+            eprintln!(
+                "dpr: {} job(s) blocked by dependencies which did not succeed",
+                blocked
+            );
+        }
         Ok(())
     }
 
-    async fn reset_given_ids(&self, ids: Vec<usize>) -> Result<()> {
-        Ok(self
-            .change_status_given_ids(ids, ExperimentStatus::NotRunning)
-            .await?)
+    pub(crate) async fn reset_all_jobs(&self, tags: &TagFilter) -> Result<()> {
+        let ids = self.store.reset_jobs(None, tags).await?;
+        self.update_blocked_jobs(ids).await
     }
 
-    pub async fn lock_table(&self) -> Result<Conn> {
-        let mut conn = self.pool.get_conn().await?;
-        conn.query_drop(format!("LOCK TABLE {} WRITE", self.table_name))
-            .await?;
-        Ok(conn)
+    pub(crate) async fn reset_jobs_with_status(
+        &self,
+        status: ExperimentStatus,
+        tags: &TagFilter,
+    ) -> Result<()> {
+        let ids = self.store.reset_jobs(Some(status), tags).await?;
+        self.update_blocked_jobs(ids).await
     }
 
-    pub async fn unlock_table(&self, mut conn: Conn) -> Result<()> {
-        conn.query_drop("UNLOCK TABLES").await?;
+    /// Changes the priority of jobs already loaded.
+    pub(crate) async fn set_priority(&self, jobs: JobSelection, priority: i32) -> Result<()> {
+        let changed = self.store.set_priority(jobs, priority).await?;
+        println!("Set the priority of {} job(s) to {}", changed, priority);
         Ok(())
     }
 
-    pub async fn change_status_given_ids_with_lock(
+    /// Claims available jobs passing the tag filter, highest priority first,
+    /// as long as they fit in the capacity left on the host.
+    pub(crate) async fn claim_available_jobs(
         &self,
-        ids: Vec<usize>,
-        new_status: ExperimentStatus,
-        conn: &mut Conn,
-    ) -> Result<()> {
-        let params = ids.iter().map(|i| {
-            params! {
-                "new_status" => new_status.to_db_code(),
-                "id" => i,
-            }
-        });
-        conn.exec_batch(
-            format!(
-                r"UPDATE {} SET status = :new_status WHERE id = :id",
-                self.table_name
-            ),
-            params,
-        )
-        .await?;
+        capacity: Capacity,
+        tags: &TagFilter,
+        shuffle: bool,
+        worker_id: &str,
+    ) -> Result<Vec<Job>> {
+        self.store
+            .claim_jobs(capacity, tags, shuffle, worker_id)
+            .await
+    }
+
+    /// Records that the worker is alive. Its jobs can be reclaimed by other
+    /// runners once `lease` has passed without another heartbeat.
+    pub async fn heartbeat(&self, worker_id: &str, lease: Duration) -> Result<()> {
+        self.store.heartbeat(worker_id, lease).await
+    }
+
+    /// Makes the jobs of workers whose lease ran out available again, unless
+    /// they ran out of attempts, and returns how many there were.
+    pub async fn reclaim_lost_jobs(&self, retry: &RetryPolicy) -> Result<usize> {
+        let ids = self.store.reclaim_lost_jobs(retry.max_attempts).await?;
+        let reclaimed = ids.len();
+        self.update_blocked_jobs(ids).await?;
+        Ok(reclaimed)
+    }
+
+    pub async fn remove_worker(&self, worker_id: &str) -> Result<()> {
+        self.store.remove_worker(worker_id).await
+    }
+
+    /// Makes a job this runner did not get to finish available again,
+    /// without counting its attempt.
+    pub async fn release_job(&self, job: &Job) -> Result<()> {
+        if !self.store.release_job(job.id, &job.claim_token).await? {
+            return Err(LostLease(job.id).into());
+        }
+        Ok(())
+    }
+
+    /// Whether the commands of this table are meant to run through a shell.
+    pub async fn get_shell_mode(&self) -> Result<bool> {
+        Ok(self.store.get_setting(SHELL_SETTING).await?.as_deref() == Some("true"))
+    }
+
+    pub async fn set_shell_mode(&self, shell: bool) -> Result<()> {
+        let value = shell.then(|| "true".to_owned());
+        self.store.set_setting(SHELL_SETTING, value).await
+    }
+
+    pub async fn record_run_stats(&self, job: &Job, stats: RunStats) -> Result<()> {
+        if !self
+            .store
+            .record_run_stats(job.id, &job.claim_token, stats)
+            .await?
+        {
+            return Err(LostLease(job.id).into());
+        }
         Ok(())
     }
 
-    pub async fn change_status_given_ids(
+    /// Records that the command of a job could not be started, and why.
+    pub async fn record_launch_error(
         &self,
-        ids: Vec<usize>,
+        job: &Job,
+        error: String,
+        retry: &RetryPolicy,
+    ) -> Result<Option<Requeue>> {
+        let stats = RunStats {
+            start_time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as i64),
+            launch_error: Some(error),
+            ..Default::default()
+        };
+        self.record_run_stats(job, stats).await?;
+        self.finish_attempt(job, ExperimentStatus::LaunchError, retry)
+            .await
+    }
+
+    /// Records how an attempt of a job ended. The job is made available again
+    /// if the retry policy allows it.
+    pub async fn finish_attempt(
+        &self,
+        job: &Job,
+        status: ExperimentStatus,
+        retry: &RetryPolicy,
+    ) -> Result<Option<Requeue>> {
+        if retry.retries(job, status) {
+            self.record_attempt(
+                job,
+                ExperimentStatus::NotRunning,
+                false,
+                Some(retry.backoff.unwrap_or_default()),
+            )
+            .await?;
+            Ok(Some(Requeue::Retry))
+        } else {
+            self.record_attempt(job, status, false, None).await?;
+            Ok(None)
+        }
+    }
+
+    async fn record_attempt(
+        &self,
+        job: &Job,
         new_status: ExperimentStatus,
+        timed_out: bool,
+        retry_delay: Option<Duration>,
     ) -> Result<()> {
-        let mut conn = self.pool.get_conn().await?;
-        let params = ids.iter().map(|i| {
-            params! {
-                "new_status" => new_status.to_db_code(),
-                "id" => i,
-            }
-        });
-        conn.exec_batch(
-            format!(
-                r"UPDATE {} SET status = :new_status WHERE id = :id",
-                self.table_name
-            ),
-            params,
-        )
-        .await?;
+        if !self
+            .store
+            .record_attempt(job.id, &job.claim_token, new_status, timed_out, retry_delay)
+            .await?
+        {
+            return Err(LostLease(job.id).into());
+        }
+        if FAILED_STATUSES.contains(&new_status) {
+            self.update_blocked_jobs(vec![job.id]).await?;
+        }
         Ok(())
     }
 
-    pub async fn get_available_jobs_with_lock(
+    /// Time limits given to a job after each of its timeouts, empty if the
+    /// table has no timeout ladder.
+    pub async fn get_timeout_ladder(&self) -> Result<Vec<Duration>> {
+        match self.store.get_setting(TIMEOUT_LADDER_SETTING).await? {
+            Some(ladder) => parse_timeout_ladder(&ladder),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Sets the timeout ladder from a comma-separated list of durations, or
+    /// removes it if the list is empty.
+    pub async fn set_timeout_ladder(&self, ladder: &str) -> Result<()> {
+        let ladder = parse_timeout_ladder(ladder)?;
+        let value = (!ladder.is_empty()).then(|| {
+            ladder
+                .iter()
+                .map(|d| d.as_secs().to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+        self.store.set_setting(TIMEOUT_LADDER_SETTING, value).await
+    }
+
+    /// Records that a job was killed for running over its time limit. The job
+    /// is made available again for the next tier of the timeout ladder, if
+    /// any, or if the retry policy allows it. It is timed out for good
+    /// otherwise.
+    pub async fn record_timeout(
         &self,
-        nb_jobs: usize,
-        shuffle: bool,
-        conn: &mut Conn,
-    ) -> Result<Vec<Job>> {
-        let cmd = if shuffle {
-            format!(
-                "SELECT id, command from {} WHERE status = :status ORDER BY RAND() LIMIT :limit ",
-                self.table_name
+        job: &Job,
+        ladder: &[Duration],
+        retry: &RetryPolicy,
+    ) -> Result<Option<Requeue>> {
+        let (new_status, retry_delay, requeue) = if job.timeout_count + 1 < ladder.len() {
+            (
+                ExperimentStatus::NotRunning,
+                None,
+                Some(Requeue::NextTimeout),
             )
-        } else {
-            format!(
-                "SELECT id, command from {} WHERE status = :status LIMIT :limit",
-                self.table_name
+        } else if retry.retries(job, ExperimentStatus::TimedOut) {
+            (
+                ExperimentStatus::NotRunning,
+                Some(retry.backoff.unwrap_or_default()),
+                Some(Requeue::Retry),
             )
+        } else {
+            (ExperimentStatus::TimedOut, None, None)
         };
-        let jobs: Vec<Job> = conn
-            .exec_map(
-                cmd,
-                params! {
-                    "status" => ExperimentStatus::NotRunning.to_db_code(),
-                    "limit" => nb_jobs
-                },
-                |(id, command)| Job {
-                    id,
-                    command: Arc::new(command),
-                },
-            )
+        self.record_attempt(job, new_status, true, retry_delay)
             .await?;
+        Ok(requeue)
+    }
+
+    /// The retry policy of the table. Failed jobs are retried by default
+    /// once a maximum number of attempts is set.
+    pub async fn get_retry_policy(&self) -> Result<RetryPolicy> {
+        let max_attempts = match self.store.get_setting(MAX_ATTEMPTS_SETTING).await? {
+            Some(max) => Some(max.parse()?),
+            None => None,
+        };
+        let retry_on = match self.store.get_setting(RETRY_ON_SETTING).await? {
+            Some(statuses) => parse_retry_statuses(&statuses)?,
+            None => vec![ExperimentStatus::FailedFinished],
+        };
+        let backoff = match self.store.get_setting(RETRY_BACKOFF_SETTING).await? {
+            Some(backoff) => Some(Duration::from_secs(backoff.parse()?)),
+            None => None,
+        };
+        Ok(RetryPolicy {
+            max_attempts,
+            retry_on,
+            backoff,
+        })
+    }
 
-        Ok(jobs)
+    /// Sets how many runs a job gets at most, or stops retrying jobs if it
+    /// is 0.
+    pub async fn set_max_attempts(&self, max_attempts: usize) -> Result<()> {
+        let value = (max_attempts > 0).then(|| max_attempts.to_string());
+        self.store.set_setting(MAX_ATTEMPTS_SETTING, value).await
     }
 
-    pub async fn get_number_of_available_jobs(&self) -> Result<Option<usize>> {
-        let mut conn = self.pool.get_conn().await?;
-        let job_count: Option<usize> = conn
-            .exec_first(
-                format!(
-                    "SELECT COUNT(*) FROM {} WHERE status = :status",
-                    self.table_name
-                ),
-                params! {
-                    "status" => ExperimentStatus::NotRunning.to_db_code(),
-                },
-            )
-            .await?;
+    /// Sets which statuses are retried from a comma-separated list of
+    /// `failed`, `timeout`, `killed` and `launch-error`.
+    pub async fn set_retry_on(&self, statuses: &str) -> Result<()> {
+        let statuses = parse_retry_statuses(statuses)?;
+        let value = RETRIABLE_STATUSES
+            .iter()
+            .filter(|(_, status)| statuses.contains(status))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(",");
+        self.store.set_setting(RETRY_ON_SETTING, Some(value)).await
+    }
+
+    /// Sets how long retried jobs wait before they can be claimed again, or
+    /// removes the wait if it is zero.
+    pub async fn set_retry_backoff(&self, backoff: Duration) -> Result<()> {
+        let value = (!backoff.is_zero()).then(|| backoff.as_secs().to_string());
+        self.store.set_setting(RETRY_BACKOFF_SETTING, value).await
+    }
+
+    /// Counts the available jobs passing the tag filter which a host with the
+    /// given capacity can run. Jobs larger than the host are left to other
+    /// runners.
+    pub(crate) async fn get_number_of_available_jobs(
+        &self,
+        host: Capacity,
+        tags: &TagFilter,
+    ) -> Result<usize> {
+        self.store.count_fitting_jobs(host, tags).await
+    }
+}
+
+/// Attributes given to the commands of a file by directives, or to the
+/// command of a record of a structured file.
+#[derive(Clone)]
+struct JobAttributes {
+    timeout: Option<Duration>,
+    cpus: usize,
+    mem: u64,
+    priority: i32,
+    label: Option<String>,
+    after: Vec<Dependency>,
+    tags: Vec<String>,
+}
+
+impl Default for JobAttributes {
+    fn default() -> Self {
+        JobAttributes {
+            timeout: None,
+            cpus: 1,
+            mem: 0,
+            priority: 0,
+            label: None,
+            after: vec![],
+            tags: vec![],
+        }
+    }
+}
+
+impl JobAttributes {
+    /// Sets the attribute named `key`, or clears it if `value` is empty.
+    /// Returns false for unknown keys.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool> {
+        match key {
+            "timeout" => {
+                self.timeout = match value {
+                    "" => None,
+                    value => Some(parse_duration(value)?),
+                }
+            }
+            "cpus" => {
+                self.cpus = match value {
+                    "" => 1,
+                    value => value
+                        .parse()
+                        .ok()
+                        .filter(|&cpus| cpus > 0)
+                        .ok_or_else(|| DatabaseError::InvalidCpus(value.to_owned()))?,
+                }
+            }
+            "mem" => {
+                self.mem = match value {
+                    "" => 0,
+                    value => parse_size(value)?,
+                }
+            }
+            "priority" => {
+                self.priority = match value {
+                    "" => 0,
+                    value => value
+                        .parse()
+                        .map_err(|_| DatabaseError::InvalidPriority(value.to_owned()))?,
+                }
+            }
+            "label" => {
+                self.label = match value {
+                    "" => None,
+                    value => Some(parse_label(value)?),
+                }
+            }
+            "after" => {
+                self.after = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|dependency| !dependency.is_empty())
+                    .map(parse_dependency)
+                    .collect::<Result<_>>()?
+            }
+            "tags" => self.tags = parse_tags(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn entry(&self, command: String) -> TableEntry {
+        TableEntry {
+            command,
+            status: ExperimentStatus::NotRunning,
+            timeout: self.timeout,
+            cpus: self.cpus,
+            mem: self.mem,
+            priority: self.priority,
+            label: self.label.clone(),
+            after: self.after.clone(),
+            tags: self.tags.clone(),
+            params: vec![],
+            cwd: None,
+            env: vec![],
+            copy: 0,
+        }
+    }
+}
+
+/// Jobs of a plain text commands file, one per line apart from directives.
+fn parse_commands(file_contents: &str) -> Result<Vec<TableEntry>> {
+    let mut attributes = JobAttributes::default();
+    let mut table_entries = vec![];
+    for line in file_contents.lines() {
+        if let Some((key, value)) = parse_directive(line) {
+            attributes.set(key, value)?;
+            continue;
+        }
+        table_entries.push(attributes.entry(line.trim_end().to_owned()));
+    }
+    Ok(table_entries)
+}
+
+/// Job of the `n`-th record of a structured job file, counting from 1.
+fn record_entry(n: usize, record: Record) -> Result<TableEntry> {
+    let mut attributes = JobAttributes::default();
+    for (key, value) in &record.attributes {
+        if !attributes.set(key, value)? {
+            return Err(DatabaseError::UnknownField(key.to_owned()).into());
+        }
+    }
+    let command = record
+        .command
+        .filter(|command| !command.trim().is_empty())
+        .ok_or(DatabaseError::MissingCommand(n))?;
+    Ok(TableEntry {
+        cwd: record.cwd,
+        env: record.env,
+        ..attributes.entry(command)
+    })
+}
+
+/// Splits a `#key: value` directive line of a commands file into its key and
+/// trimmed value. Lines with any other key are commands, as they were before
+/// directives existed.
+fn parse_directive(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.strip_prefix('#')?.split_once(':')?;
+    DIRECTIVES.contains(&key).then(|| (key, value.trim()))
+}
+
+/// Labels cannot be taken for ids, nor split by `#after:`.
+fn parse_label(label: &str) -> Result<String> {
+    if label.chars().all(|c| c.is_ascii_digit()) || label.contains(',') {
+        return Err(DatabaseError::InvalidLabel(label.to_owned()).into());
+    }
+    Ok(label.to_owned())
+}
+
+/// Fails if the jobs of a file, given as pairs of positions, depend on each
+/// other in a cycle, as none of them could ever run. Jobs already in the table
+/// cannot depend on new ones, so cycles can only go through the file.
+fn check_acyclic(job_count: usize, dependencies: &[(usize, usize)]) -> Result<()> {
+    let mut waiting_on = vec![0; job_count];
+    let mut dependants = vec![vec![]; job_count];
+    for &(i, j) in dependencies {
+        waiting_on[i] += 1;
+        dependants[j].push(i);
+    }
+    let mut ready: Vec<usize> = (0..job_count).filter(|&i| waiting_on[i] == 0).collect();
+    let mut done = 0;
+    while let Some(j) = ready.pop() {
+        done += 1;
+        for &i in &dependants[j] {
+            waiting_on[i] -= 1;
+            if waiting_on[i] == 0 {
+                ready.push(i);
+            }
+        }
+    }
+    if done < job_count {
+        return Err(DatabaseError::DependencyCycle.into());
+    }
+    Ok(())
+}
+
+/// Tags fit in their column, and cannot be mistaken for lists of tags.
+pub(crate) fn parse_tag(tag: &str) -> Result<String> {
+    if tag.is_empty() || tag.len() > 64 || tag.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(DatabaseError::InvalidTag(tag.to_owned()).into());
+    }
+    Ok(tag.to_owned())
+}
+
+/// Comma-separated tags of a `#tags:` directive, without duplicates.
+fn parse_tags(tags: &str) -> Result<Vec<String>> {
+    let mut tags = tags
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(parse_tag)
+        .collect::<Result<Vec<_>>>()?;
+    tags.sort_unstable();
+    tags.dedup();
+    Ok(tags)
+}
+
+/// A number is the id of a job, anything else a label.
+fn parse_dependency(dependency: &str) -> Result<Dependency> {
+    match dependency.parse() {
+        Ok(id) => Ok(Dependency::Id(id)),
+        Err(_) => parse_label(dependency).map(Dependency::Label),
+    }
+}
+
+fn parse_retry_statuses(statuses: &str) -> Result<Vec<ExperimentStatus>> {
+    statuses
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            RETRIABLE_STATUSES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|&(_, status)| status)
+                .ok_or_else(|| DatabaseError::UnknownRetryStatus(name.to_owned()).into())
+        })
+        .collect()
+}
+
+fn parse_timeout_ladder(ladder: &str) -> Result<Vec<Duration>> {
+    ladder
+        .split(',')
+        .map(str::trim)
+        .filter(|tier| !tier.is_empty())
+        .map(|tier| Ok(parse_duration(tier)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_directives_as_commands() {
+        let entries =
+            parse_commands("#note: sweep of May\n#timeout: 10m\n./solver a.cnf\n#TODO: rerun\n")
+                .unwrap();
+        let commands: Vec<&str> = entries.iter().map(|t| t.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["#note: sweep of May", "./solver a.cnf", "#TODO: rerun"]
+        );
+        assert_eq!(entries[0].timeout, None);
+        assert_eq!(entries[1].timeout, Some(Duration::from_secs(600)));
+        assert!(parse_commands("#cpus: none\n./solver a.cnf\n").is_err());
+    }
 
-        Ok(job_count)
+    #[test]
+    fn rejects_records_without_a_command() {
+        let records = jobfile::parse_records("cpus\n2\n", JobFormat::Csv).unwrap();
+        let error = record_entry(1, records.into_iter().next().unwrap())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast().unwrap(),
+            DatabaseError::MissingCommand(1)
+        ));
+        let records =
+            jobfile::parse_records("[[job]]\ncommand = \" \"\nmem = \"1G\"\n", JobFormat::Toml)
+                .unwrap();
+        let error = record_entry(1, records.into_iter().next().unwrap())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast().unwrap(),
+            DatabaseError::MissingCommand(1)
+        ));
     }
 }
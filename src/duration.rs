@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error(
+    "Invalid duration `{0}`, expected a number of seconds or a number followed by s, m, h or d"
+)]
+pub(crate) struct ParseDurationError(String);
+
+/// Parses durations such as `90`, `90s`, `10m`, `4h` or `2d`. A bare number is
+/// a number of seconds.
+pub(crate) fn parse_duration(s: &str) -> Result<Duration, ParseDurationError> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(ParseDurationError(s.to_owned())),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_secs))
+        .map(Duration::from_secs)
+        .ok_or_else(|| ParseDurationError(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_with_and_without_units() {
+        for (s, secs) in [
+            ("90", 90),
+            ("90s", 90),
+            ("10m", 600),
+            (" 4 h ", 4 * 3600),
+            ("2d", 2 * 86400),
+            ("0", 0),
+        ] {
+            assert_eq!(
+                parse_duration(s).unwrap(),
+                Duration::from_secs(secs),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_numbers_and_units() {
+        for s in [
+            "",
+            "s",
+            "10ms",
+            "10x",
+            "1.5h",
+            "-1",
+            "1h30m",
+            "99999999999999999999d",
+        ] {
+            assert!(parse_duration(s).is_err(), "{}", s);
+        }
+    }
+}
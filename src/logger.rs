@@ -1,10 +1,8 @@
 use crate::process::{ExperimentProcess, ProcessResult};
 
 use anyhow::Result;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, future::Future, sync::Arc};
 use tokio::{
-    fs::File,
-    io::AsyncWriteExt,
     sync::{mpsc::Receiver, Mutex},
     task::JoinHandle,
 };
@@ -15,20 +13,9 @@ pub(crate) struct TrackerLogger {
 }
 
 impl TrackerLogger {
-    pub(crate) async fn new(
-        writer_rx: Receiver<ProcessResult>,
-        log_folder: Option<String>,
-    ) -> Result<TrackerLogger> {
+    pub(crate) async fn new(writer_rx: Receiver<ProcessResult>) -> Result<TrackerLogger> {
         let active_jobs = Arc::new(Mutex::new(HashMap::new()));
-        let log_folder = Arc::new(log_folder);
-        if let Some(logs) = log_folder.as_ref() {
-            tokio::fs::create_dir_all(logs).await?;
-        }
-        let track_task = tokio::spawn(TrackerLogger::worker_writer(
-            writer_rx,
-            active_jobs.clone(),
-            log_folder.clone(),
-        ));
+        let track_task = tokio::spawn(TrackerLogger::worker_writer(writer_rx, active_jobs.clone()));
         Ok(TrackerLogger {
             track_task,
             active_jobs,
@@ -38,15 +25,8 @@ impl TrackerLogger {
     async fn worker_writer(
         mut rx: Receiver<ProcessResult>,
         active_jobs: Arc<Mutex<HashMap<usize, ExperimentProcess>>>,
-        log_folder: Arc<Option<String>>,
     ) -> Result<()> {
         while let Some(p) = rx.recv().await {
-            if let Some(logs) = log_folder.as_ref() {
-                let mut stdout_file = File::create(format!("{}/{}.out", logs, p.job.id)).await?;
-                stdout_file.write_all(p.stdout.as_bytes()).await?;
-                let mut stderr_file = File::create(format!("{}/{}.err", logs, p.job.id)).await?;
-                stderr_file.write_all(p.stderr.as_bytes()).await?;
-            }
             let mut lock = active_jobs.lock().await;
             let p = (*lock).remove(&p.job.id);
             drop(lock);
@@ -57,9 +37,21 @@ impl TrackerLogger {
         Ok(())
     }
 
-    pub(crate) async fn add_to_active_jobs(&self, p: ExperimentProcess) {
+    /// Starts a job and tracks it. The job is started with the active jobs
+    /// locked, so a job failing right away cannot report its result before it
+    /// is tracked.
+    pub(crate) async fn add_to_active_jobs(
+        &self,
+        start: impl Future<Output = Result<ExperimentProcess>>,
+    ) -> Result<()> {
         let mut lock = self.active_jobs.lock().await;
+        let p = start.await?;
         (*lock).insert(p.job.id, p);
+        Ok(())
+    }
+
+    pub(crate) async fn has_active_jobs(&self) -> bool {
+        !self.active_jobs.lock().await.is_empty()
     }
 
     pub(crate) async fn wait_all_to_finish(&self) -> Result<()> {
@@ -1,13 +1,28 @@
 mod db;
+mod duration;
 mod logger;
 mod process;
+mod shutdown;
+mod size;
+mod template;
 
 use anyhow::Result;
-use db::ExperimentStatus;
+use db::{
+    parse_tag, Capacity, ExperimentDatabase, ExperimentStatus, JobFormat, JobSelection,
+    OnDuplicate, TagFilter,
+};
+use duration::parse_duration;
 use logger::TrackerLogger;
-use process::ExperimentProcess;
+use process::{ExperimentProcess, RunOptions};
+use shutdown::Shutdown;
+use size::parse_size;
+use template::{parse_param, Param};
 
-use std::{path::PathBuf, sync::atomic::Ordering, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 use structopt::StructOpt;
 use tokio::sync::mpsc;
 
@@ -15,9 +30,15 @@ use tokio::sync::mpsc;
 #[derive(StructOpt, Debug)]
 #[structopt(name = "dpr")]
 struct Opt {
-    /// DB Configuration file.
+    /// DB Configuration file. Looked up in DPR_CONFIG, $XDG_CONFIG_HOME/dpr/config.toml and ~/.dpr.toml if not given
     #[structopt(short, long)]
-    config: PathBuf,
+    config: Option<PathBuf>,
+    /// Connection profile to use from the configuration file instead of [client]
+    #[structopt(long)]
+    profile: Option<String>,
+    /// MySQL option file (like ~/.my.cnf) to read missing connection settings from
+    #[structopt(long)]
+    defaults_file: Option<PathBuf>,
     /// Table to use
     #[structopt(short = "n", long, default_value = "experiments")]
     table_name: String,
@@ -38,6 +59,26 @@ enum Command {
         /// Commands file to load
         #[structopt(short = "l", long = "load")]
         commands_file_to_load: Option<PathBuf>,
+        /// Format of the commands file, among text, csv, jsonl and toml [default: from its extension, else text]
+        #[structopt(long, requires = "commands-file-to-load")]
+        format: Option<JobFormat>,
+        /// What to do with jobs already in the table: error, skip them, or requeue those which ended
+        #[structopt(long, default_value = "error")]
+        on_duplicate: OnDuplicate,
+        /// Load one job per combination of --param values, e.g. 'solver --seed {seed} {file}'
+        #[structopt(long, requires = "param", conflicts_with = "commands-file-to-load")]
+        load_template: Option<String>,
+        /// Values of a template parameter: name=a,b,c, name=1..10, name=0..100:10, name=lines:file or name=glob:pattern
+        #[structopt(
+            long,
+            number_of_values = 1,
+            parse(try_from_str = parse_param),
+            requires = "load-template"
+        )]
+        param: Vec<Param>,
+        /// Pair the n-th values of every parameter instead of taking all their combinations
+        #[structopt(long, requires = "load-template")]
+        zip: bool,
         /// Reset running jobs to available in DB
         #[structopt(long, group = "reset")]
         reset_running: bool,
@@ -47,9 +88,68 @@ enum Command {
         /// Reset timed out jobs to available in DB
         #[structopt(long, group = "reset")]
         reset_timeout: bool,
+        /// Reset jobs killed by a signal to available in DB
+        #[structopt(long, group = "reset")]
+        reset_killed: bool,
+        /// Reset jobs which could not be started to available in DB
+        #[structopt(long, group = "reset")]
+        reset_launch_error: bool,
         /// Reset all jobs to available in DB
         #[structopt(long, group = "reset")]
         reset_all: bool,
+        /// Upgrade the table to the latest schema version, keeping its jobs
+        #[structopt(long, group = "reset")]
+        migrate: bool,
+        /// Create the table with this older schema version, loading only the commands, to test migrations
+        #[structopt(long, hidden = true, requires = "create-table")]
+        legacy_schema: Option<usize>,
+        /// Only reset the jobs carrying all these tags, separated by commas
+        #[structopt(
+            long,
+            use_delimiter = true,
+            parse(try_from_str = parse_tag),
+            requires = "reset",
+            conflicts_with_all = &["create-table", "migrate"]
+        )]
+        tag: Vec<String>,
+        /// Only reset the jobs carrying none of these tags, separated by commas
+        #[structopt(
+            long,
+            use_delimiter = true,
+            parse(try_from_str = parse_tag),
+            requires = "reset",
+            conflicts_with_all = &["create-table", "migrate"]
+        )]
+        exclude_tag: Vec<String>,
+        /// Run the commands of this table through `sh -c` (true) or directly (false)
+        #[structopt(long, parse(try_from_str))]
+        set_shell: Option<bool>,
+        /// Time limits of the successive runs of jobs timing out (e.g. 10m,1h,4h), empty to remove them
+        #[structopt(long)]
+        set_timeout_ladder: Option<String>,
+        /// Runs a job gets at most before it stays failed, 0 to not retry jobs
+        #[structopt(long)]
+        set_max_attempts: Option<usize>,
+        /// Statuses which are retried, among failed, timeout, killed and launch-error [default: failed]
+        #[structopt(long)]
+        set_retry_on: Option<String>,
+        /// Time retried jobs wait before they run again (e.g. 30s, 10m), 0 for none
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        set_retry_backoff: Option<Duration>,
+        /// Priority of the jobs given with --ids or --matching, higher ones run first
+        #[structopt(long, allow_hyphen_values = true, requires = "selection")]
+        set_priority: Option<i32>,
+        /// Ids of the jobs to change, separated by commas
+        #[structopt(
+            long,
+            use_delimiter = true,
+            group = "selection",
+            requires = "set-priority"
+        )]
+        ids: Vec<usize>,
+        /// Change the jobs whose command contains this text, in the same case
+        #[structopt(long, group = "selection", requires = "set-priority")]
+        matching: Option<String>,
     },
     /// Run experiments in parallel
     Run {
@@ -59,12 +159,36 @@ enum Command {
         /// Number of parallel of jobs on run mode
         #[structopt(short = "j", long = "jobs", default_value = "1")]
         nb_jobs: usize,
+        /// Cores the running jobs can declare in total [default: detected]
+        #[structopt(long)]
+        cpus: Option<usize>,
+        /// Memory the running jobs can declare in total (e.g. 512M, 64G) [default: detected]
+        #[structopt(long, parse(try_from_str = parse_size))]
+        mem: Option<u64>,
         /// Keep it running even though the DB is empty and no tasks are running
         #[structopt(short, long)]
         keep_running: bool,
         /// Dump command line outputs of tasks.
         #[structopt(short, long)]
         log_folder: Option<String>,
+        /// Run commands through `sh -c`, even if the table does not enable shell mode
+        #[structopt(long)]
+        shell: bool,
+        /// Kill jobs running longer than this (e.g. 90s, 10m, 4h) unless they set their own timeout, instead of following the timeout ladder
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        timeout: Option<Duration>,
+        /// Time jobs get to exit on SIGINT or SIGTERM before they are killed and made available again
+        #[structopt(long, default_value = "10s", parse(try_from_str = parse_duration))]
+        grace_period: Duration,
+        /// Time without a heartbeat after which other runners make the jobs of this one available again
+        #[structopt(long, default_value = "5m", parse(try_from_str = parse_duration))]
+        lease: Duration,
+        /// Only run the jobs carrying all these tags, separated by commas
+        #[structopt(long, use_delimiter = true, parse(try_from_str = parse_tag))]
+        tag: Vec<String>,
+        /// Only run the jobs carrying none of these tags, separated by commas
+        #[structopt(long, use_delimiter = true, parse(try_from_str = parse_tag))]
+        exclude_tag: Vec<String>,
     },
     /// Print out stats or experiment details
     Show {
@@ -74,95 +198,491 @@ enum Command {
         /// Print all experiments in the DB
         #[structopt(long, group = "print")]
         all: bool,
+        /// With --all, also print the exit code, signal, times and peak memory of the last run
+        #[structopt(long, requires = "all")]
+        details: bool,
+        /// Print every experiment with the ids of the experiments it still waits on
+        #[structopt(long, group = "print")]
+        dependencies: bool,
+        /// Print experiment statistics for each value of this template parameter
+        #[structopt(long, group = "print")]
+        group_by: Option<String>,
+        /// With --stats, only count the experiments carrying all these tags, separated by commas
+        #[structopt(
+            long,
+            use_delimiter = true,
+            parse(try_from_str = parse_tag),
+            requires = "stats"
+        )]
+        tag: Vec<String>,
+        /// With --stats, only count the experiments carrying none of these tags, separated by commas
+        #[structopt(
+            long,
+            use_delimiter = true,
+            parse(try_from_str = parse_tag),
+            requires = "stats"
+        )]
+        exclude_tag: Vec<String>,
     },
 }
 
 #[tokio::main]
 pub async fn main() -> Result<()> {
     let opt = Opt::from_args();
-    let db_config = db::DatabaseConfig::from_config_file(&opt.config).await?;
+    let config = match opt.config {
+        Some(config) => config,
+        None => db::DatabaseConfig::find_config_file()?,
+    };
+    let db_config = db::DatabaseConfig::from_config_file(
+        &config,
+        opt.profile.as_deref(),
+        opt.defaults_file.as_deref(),
+    )
+    .await?;
     let experiment_db = db::ExperimentDatabase::from_db_config(db_config, opt.table_name)?;
+    execute(opt.command, opt.shuffle, &experiment_db).await?;
+    experiment_db.disconnect().await?;
+    Ok(())
+}
 
-    match opt.command {
+/// Runs a subcommand against an experiment table, whichever backend stores it.
+async fn execute(
+    command: Command,
+    shuffle: bool,
+    experiment_db: &ExperimentDatabase,
+) -> Result<()> {
+    match command {
         Command::Edit {
             create_table,
-            commands_file_to_load,
+            mut commands_file_to_load,
+            format,
+            on_duplicate,
+            load_template,
+            param,
+            zip,
             reset_running,
             reset_failed,
             reset_timeout,
+            reset_killed,
+            reset_launch_error,
             reset_all,
+            migrate,
+            legacy_schema,
+            tag,
+            exclude_tag,
+            set_shell,
+            set_timeout_ladder,
+            set_max_attempts,
+            set_retry_on,
+            set_retry_backoff,
+            set_priority,
+            ids,
+            matching,
         } => {
-            if create_table {
+            let tags = TagFilter {
+                include: tag,
+                exclude: exclude_tag,
+            };
+            if let Some(version) = legacy_schema {
+                experiment_db
+                    .create_legacy_table(version, commands_file_to_load.take().as_deref())
+                    .await?;
+            } else if create_table {
                 experiment_db.create_table().await?;
             } else if reset_running {
                 experiment_db
-                    .reset_jobs_with_status(ExperimentStatus::Running)
+                    .reset_jobs_with_status(ExperimentStatus::Running, &tags)
                     .await?;
             } else if reset_failed {
                 experiment_db
-                    .reset_jobs_with_status(ExperimentStatus::FailedFinished)
+                    .reset_jobs_with_status(ExperimentStatus::FailedFinished, &tags)
                     .await?;
             } else if reset_timeout {
                 experiment_db
-                    .reset_jobs_with_status(ExperimentStatus::TimedOut)
+                    .reset_jobs_with_status(ExperimentStatus::TimedOut, &tags)
+                    .await?;
+            } else if reset_killed {
+                experiment_db
+                    .reset_jobs_with_status(ExperimentStatus::Killed, &tags)
+                    .await?;
+            } else if reset_launch_error {
+                experiment_db
+                    .reset_jobs_with_status(ExperimentStatus::LaunchError, &tags)
                     .await?;
             } else if reset_all {
-                experiment_db.reset_all_jobs().await?;
+                experiment_db.reset_all_jobs(&tags).await?;
+            } else if migrate {
+                experiment_db.migrate().await?;
+            }
+            if let Some(shell) = set_shell {
+                experiment_db.set_shell_mode(shell).await?;
+            }
+            if let Some(ladder) = set_timeout_ladder {
+                experiment_db.set_timeout_ladder(&ladder).await?;
+            }
+            if let Some(max_attempts) = set_max_attempts {
+                experiment_db.set_max_attempts(max_attempts).await?;
+            }
+            if let Some(statuses) = set_retry_on {
+                experiment_db.set_retry_on(&statuses).await?;
+            }
+            if let Some(backoff) = set_retry_backoff {
+                experiment_db.set_retry_backoff(backoff).await?;
             }
             if let Some(commands_file) = commands_file_to_load {
                 experiment_db
-                    .load_commands(&commands_file, opt.shuffle)
+                    .load_commands(&commands_file, format, on_duplicate, shuffle)
+                    .await?;
+            }
+            if let Some(command_template) = load_template {
+                experiment_db
+                    .load_template(&command_template, &param, zip, on_duplicate, shuffle)
                     .await?;
             }
+            if let Some(priority) = set_priority {
+                let jobs = match matching {
+                    Some(text) => JobSelection::Matching(text),
+                    None => JobSelection::Ids(ids),
+                };
+                experiment_db.set_priority(jobs, priority).await?;
+            }
         }
         Command::Run {
             freq,
             nb_jobs,
+            cpus,
+            mem,
             keep_running,
             log_folder,
+            shell,
+            timeout,
+            grace_period,
+            lease,
+            tag,
+            exclude_tag,
         } => {
+            anyhow::ensure!(
+                lease > Duration::from_secs(freq as u64),
+                "--lease must be longer than --freq, or other runners would take the jobs of this one"
+            );
+            experiment_db.check_schema_version().await?;
+            let host = Capacity {
+                jobs: nb_jobs,
+                cpus: cpus.unwrap_or_else(process::detect_cpus),
+                mem: mem.unwrap_or_else(process::detect_mem),
+            };
+            let tags = TagFilter {
+                include: tag,
+                exclude: exclude_tag,
+            };
+            let options = RunOptions {
+                shell: shell || experiment_db.get_shell_mode().await?,
+                timeout,
+                timeout_ladder: experiment_db.get_timeout_ladder().await?.into(),
+                log_folder: log_folder.map(|logs| Arc::from(Path::new(&logs))),
+                retry: experiment_db.get_retry_policy().await?,
+                grace_period,
+            };
+            if let Some(logs) = &options.log_folder {
+                tokio::fs::create_dir_all(logs).await?;
+            }
             let (writer_tx, writer_rx) = mpsc::channel(100);
-            let tracker = TrackerLogger::new(writer_rx, log_folder).await?;
-            while experiment_db
-                .get_number_of_available_jobs()
-                .await?
-                .filter(|&nb| nb > 0 || keep_running)
-                .is_some()
-            {
-                let nb_available = nb_jobs - process::GLOBAL_JOB_COUNT.load(Ordering::SeqCst);
-                if nb_available > 0 {
-                    let mut conn = experiment_db.lock_table().await?;
+            let tracker = TrackerLogger::new(writer_rx).await?;
+            let mut shutdown = Shutdown::listen()?;
+            let worker_id = process::worker_id();
+            loop {
+                // Every poll proves this runner alive, and takes back the jobs
+                // of runners which stopped doing so.
+                experiment_db.heartbeat(&worker_id, lease).await?;
+                let reclaimed = experiment_db.reclaim_lost_jobs(&options.retry).await?;
+                if reclaimed > 0 {
+                    eprintln!(
+                        "dpr: {} job(s) of lost runners made available again",
+                        reclaimed
+                    );
+                }
+                // Jobs of this runner may be requeued when they finish, so it
+                // keeps polling until they are all done. After SIGINT or
+                // SIGTERM, it only waits for its jobs to be stopped and made
+                // available again.
+                if shutdown.signal().is_some()
+                    || !(experiment_db
+                        .get_number_of_available_jobs(host, &tags)
+                        .await?
+                        > 0
+                        || tracker.has_active_jobs().await
+                        || keep_running)
+                {
+                    break;
+                }
+                // Jobs are claimed as long as they fit in the slots, cores and
+                // memory left by the running ones.
+                let capacity = Capacity {
+                    jobs: host
+                        .jobs
+                        .saturating_sub(process::GLOBAL_JOB_COUNT.load(Ordering::SeqCst)),
+                    cpus: host
+                        .cpus
+                        .saturating_sub(process::GLOBAL_CPUS_USED.load(Ordering::SeqCst)),
+                    mem: host
+                        .mem
+                        .saturating_sub(process::GLOBAL_MEM_USED.load(Ordering::SeqCst)),
+                };
+                if capacity.jobs > 0 {
                     let jobs = experiment_db
-                        .get_available_jobs_with_lock(nb_available, opt.shuffle, &mut conn)
+                        .claim_available_jobs(capacity, &tags, shuffle, &worker_id)
                         .await?;
-                    experiment_db
-                        .change_status_given_ids_with_lock(
-                            jobs.iter().map(|j| j.id).collect(),
-                            ExperimentStatus::Running,
-                            &mut conn,
-                        )
-                        .await?;
-                    experiment_db.unlock_table(conn).await?;
                     for j in jobs {
-                        let p = ExperimentProcess::new(j, experiment_db.clone(), writer_tx.clone())
+                        tracker
+                            .add_to_active_jobs(ExperimentProcess::new(
+                                j,
+                                experiment_db.clone(),
+                                writer_tx.clone(),
+                                options.clone(),
+                                shutdown.clone(),
+                            ))
                             .await?;
-                        tracker.add_to_active_jobs(p).await;
                     }
                 }
-                tokio::time::sleep(Duration::from_secs(freq as u64)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(freq as u64)) => {}
+                    _ = shutdown.requested() => {}
+                }
             }
             tracker.wait_all_to_finish().await?;
             drop(writer_tx);
             tracker.track_task.await??;
+            experiment_db.remove_worker(&worker_id).await?;
         }
-        Command::Show { stats, all } => {
+        Command::Show {
+            stats,
+            all,
+            details,
+            dependencies,
+            group_by,
+            tag,
+            exclude_tag,
+        } => {
             if stats {
-                experiment_db.print_stats().await?;
+                let tags = TagFilter {
+                    include: tag,
+                    exclude: exclude_tag,
+                };
+                experiment_db.print_stats(&tags).await?;
             } else if all {
-                experiment_db.print_all_jobs().await?;
+                experiment_db.print_all_jobs(details).await?;
+            } else if dependencies {
+                experiment_db.print_dependencies().await?;
+            } else if let Some(param) = group_by {
+                experiment_db.print_grouped_stats(&param).await?;
             }
         }
     }
-    experiment_db.pool.disconnect().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    /// Runs share the job counters of the process, so only one runs at a time.
+    static RUN_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    async fn dpr(experiment_db: &ExperimentDatabase, args: &[&str]) -> Result<()> {
+        let opt = Opt::from_iter_safe(["dpr"].iter().chain(args))?;
+        let _run = RUN_LOCK.lock().await;
+        execute(opt.command, opt.shuffle, experiment_db).await
+    }
+
+    /// Path of a file named after the test, in the temporary directory.
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dpr-{}-{}", std::process::id(), name))
+    }
+
+    fn commands_file(name: &str, commands: &str) -> PathBuf {
+        let path = temp_file(name);
+        fs::write(&path, commands).unwrap();
+        path
+    }
+
+    async fn statuses(experiment_db: &ExperimentDatabase) -> Vec<(ExperimentStatus, usize)> {
+        experiment_db
+            .get_all_jobs()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|job| (job.status, job.attempts))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn runs_loaded_jobs() {
+        let experiment_db = ExperimentDatabase::in_memory();
+        let commands = commands_file("runs_loaded_jobs", "true\nfalse\nsh -c 'exit 3'\n");
+        let load = commands.to_str().unwrap();
+        dpr(&experiment_db, &["edit", "--create-table", "--load", load])
+            .await
+            .unwrap();
+        dpr(&experiment_db, &["edit", "--load", load])
+            .await
+            .unwrap_err();
+        dpr(&experiment_db, &["run", "--freq", "0", "--jobs", "2"])
+            .await
+            .unwrap();
+        dpr(&experiment_db, &["show", "--stats"]).await.unwrap();
+        dpr(&experiment_db, &["show", "--all", "--details"])
+            .await
+            .unwrap();
+        let jobs = experiment_db.get_all_jobs().await.unwrap();
+        assert_eq!(
+            jobs.iter().map(|job| job.status).collect::<Vec<_>>(),
+            [
+                ExperimentStatus::SuccessFinished,
+                ExperimentStatus::FailedFinished,
+                ExperimentStatus::FailedFinished
+            ]
+        );
+        assert_eq!(jobs[2].run.exit_code, Some(3));
+        fs::remove_file(commands).unwrap();
+    }
+
+    #[tokio::test]
+    async fn claims_highest_priority_first_within_capacity() {
+        let experiment_db = ExperimentDatabase::in_memory();
+        let order = temp_file("claims_order");
+        let commands = commands_file(
+            "claims_highest_priority_first_within_capacity",
+            &format!(
+                "sh -c 'echo low >> {0}'\n#priority: 5\nsh -c 'echo high >> {0}'\n\
+                #priority:\n#tags: other\nsh -c 'echo other >> {0}'\n#tags:\n#cpus: 64\ntrue\n",
+                order.display()
+            ),
+        );
+        dpr(
+            &experiment_db,
+            &[
+                "edit",
+                "--create-table",
+                "--load",
+                commands.to_str().unwrap(),
+            ],
+        )
+        .await
+        .unwrap();
+        dpr(
+            &experiment_db,
+            &[
+                "run",
+                "--freq",
+                "0",
+                "--cpus",
+                "2",
+                "--exclude-tag",
+                "other",
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read_to_string(&order).unwrap(), "high\nlow\n");
+        assert_eq!(
+            statuses(&experiment_db).await,
+            [
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::NotRunning, 0),
+                (ExperimentStatus::NotRunning, 0)
+            ]
+        );
+        fs::remove_file(commands).unwrap();
+        fs::remove_file(order).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_failed_jobs() {
+        let experiment_db = ExperimentDatabase::in_memory();
+        let commands = commands_file("retries_failed_jobs", "false\ntrue\n");
+        dpr(
+            &experiment_db,
+            &[
+                "edit",
+                "--create-table",
+                "--load",
+                commands.to_str().unwrap(),
+            ],
+        )
+        .await
+        .unwrap();
+        dpr(&experiment_db, &["edit", "--set-max-attempts", "3"])
+            .await
+            .unwrap();
+        dpr(&experiment_db, &["run", "--freq", "0"]).await.unwrap();
+        assert_eq!(
+            statuses(&experiment_db).await,
+            [
+                (ExperimentStatus::FailedFinished, 3),
+                (ExperimentStatus::SuccessFinished, 1)
+            ]
+        );
+        dpr(&experiment_db, &["edit", "--reset-failed"])
+            .await
+            .unwrap();
+        assert_eq!(
+            statuses(&experiment_db).await[0].0,
+            ExperimentStatus::NotRunning
+        );
+        fs::remove_file(commands).unwrap();
+    }
+
+    #[tokio::test]
+    async fn runs_dependants_after_their_dependencies() {
+        let experiment_db = ExperimentDatabase::in_memory();
+        let commands = commands_file(
+            "runs_dependants_after_their_dependencies",
+            "#label: failing\nfalse\n#label: passing\ntrue\n#label: blocked\n\
+            #after: failing\ntrue\n#label:\n#after: passing\ntrue\n#after: blocked\ntrue\n",
+        );
+        dpr(
+            &experiment_db,
+            &[
+                "edit",
+                "--create-table",
+                "--load",
+                commands.to_str().unwrap(),
+            ],
+        )
+        .await
+        .unwrap();
+        dpr(&experiment_db, &["show", "--dependencies"])
+            .await
+            .unwrap();
+        dpr(&experiment_db, &["run", "--freq", "0", "--jobs", "4"])
+            .await
+            .unwrap();
+        assert_eq!(
+            statuses(&experiment_db).await,
+            [
+                (ExperimentStatus::FailedFinished, 1),
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::Blocked, 0),
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::Blocked, 0)
+            ]
+        );
+        dpr(&experiment_db, &["edit", "--reset-failed"])
+            .await
+            .unwrap();
+        assert_eq!(
+            statuses(&experiment_db).await,
+            [
+                (ExperimentStatus::NotRunning, 0),
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::NotRunning, 0),
+                (ExperimentStatus::SuccessFinished, 1),
+                (ExperimentStatus::NotRunning, 0)
+            ]
+        );
+        fs::remove_file(commands).unwrap();
+    }
+}
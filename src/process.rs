@@ -1,26 +1,90 @@
-use crate::db::{ExperimentDatabase, ExperimentStatus, Job};
+use crate::{
+    db::{ExperimentDatabase, ExperimentStatus, Job, LostLease, Requeue, RetryPolicy, RunStats},
+    shutdown::Shutdown,
+};
 
 use std::{
-    process::{ExitStatus, Stdio},
-    sync::atomic::{AtomicUsize, Ordering},
+    io, mem,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Result;
 use thiserror::Error;
 
 use tokio::{
-    io::AsyncReadExt,
-    io::BufReader,
-    process::Child,
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{ChildStderr, ChildStdout},
     sync::mpsc::Sender,
     task::{self, JoinHandle},
+    time,
 };
 
+/// Jobs claimed by this runner which did not finish yet, and the cores and
+/// memory, in MiB, they declared. They are reserved as soon as the jobs are
+/// claimed, so the next poll does not claim them again before the jobs start.
 pub(crate) static GLOBAL_JOB_COUNT: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static GLOBAL_CPUS_USED: AtomicUsize = AtomicUsize::new(0);
+pub(crate) static GLOBAL_MEM_USED: AtomicU64 = AtomicU64::new(0);
+
+/// Process groups of the started jobs which were not reaped yet. Each job runs
+/// in its own group, led by the process spawned for it, so signals reach the
+/// processes it starts as well.
+static JOB_GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
 
+/// Return code reported for a job dpr killed for running over its time
+/// limit, as coreutils `timeout` does.
 const TIMEOUT_RETURN_CODE: i32 = 124;
 
-use tokio::process::Command;
+/// Bytes of each output stream of a job kept in memory, to be printed when the
+/// job finishes. Only the log files get the whole output.
+const OUTPUT_MEMORY_LIMIT: usize = 64 * 1024;
+
+/// Settings of `dpr run` applying to every job it starts.
+#[derive(Clone, Debug)]
+pub(crate) struct RunOptions {
+    /// Run commands through `sh -c` instead of executing them directly.
+    pub(crate) shell: bool,
+    /// Time limit of jobs without a timeout of their own.
+    pub(crate) timeout: Option<Duration>,
+    /// Time limits of the table for a job's successive timeouts.
+    pub(crate) timeout_ladder: Arc<[Duration]>,
+    /// Folder receiving the stdout and stderr of every job.
+    pub(crate) log_folder: Option<Arc<Path>>,
+    /// When jobs which did not succeed are run again.
+    pub(crate) retry: RetryPolicy,
+    /// Time jobs get to exit after the runner forwards them a stop signal,
+    /// before they are killed.
+    pub(crate) grace_period: Duration,
+}
+
+impl RunOptions {
+    /// The timeout ladder of the table, unless the job has a timeout of its
+    /// own or the runner a default one, which both take precedence over it.
+    fn timeout_ladder(&self, job: &Job) -> &[Duration] {
+        if job.timeout.is_some() || self.timeout.is_some() {
+            &[]
+        } else {
+            &self.timeout_ladder
+        }
+    }
+
+    /// The own timeout of the job, or else the default one of the runner, or
+    /// else the tier of the timeout ladder the job has reached.
+    fn time_limit(&self, job: &Job) -> Option<Duration> {
+        job.timeout.or(self.timeout).or_else(|| {
+            let ladder = self.timeout_ladder(job);
+            ladder.get(job.timeout_count).or(ladder.last()).copied()
+        })
+    }
+}
 
 #[derive(Debug)]
 pub struct ExperimentProcess {
@@ -33,11 +97,18 @@ impl ExperimentProcess {
         job: Job,
         experiment_db: ExperimentDatabase,
         writer_tx: Sender<ProcessResult>,
+        options: RunOptions,
+        shutdown: Shutdown,
     ) -> Result<ExperimentProcess> {
+        GLOBAL_JOB_COUNT.fetch_add(1, Ordering::SeqCst);
+        GLOBAL_CPUS_USED.fetch_add(job.cpus, Ordering::SeqCst);
+        GLOBAL_MEM_USED.fetch_add(job.mem, Ordering::SeqCst);
         let task = task::spawn(ExperimentProcess::middle_layer(
             job.clone(),
             experiment_db,
             writer_tx,
+            options,
+            shutdown,
         ));
         Ok(ExperimentProcess { job, task })
     }
@@ -46,99 +117,473 @@ impl ExperimentProcess {
         job: Job,
         experiment_db: ExperimentDatabase,
         writer_tx: Sender<ProcessResult>,
+        options: RunOptions,
+        shutdown: Shutdown,
     ) -> Result<()> {
-        let worker_result = ExperimentProcess::worker(job.clone(), experiment_db).await;
+        let log_folder = options.log_folder.clone();
+        let worker_result =
+            ExperimentProcess::worker(job.clone(), experiment_db, options, shutdown).await;
+        GLOBAL_JOB_COUNT.fetch_sub(1, Ordering::SeqCst);
+        GLOBAL_CPUS_USED.fetch_sub(job.cpus, Ordering::SeqCst);
+        GLOBAL_MEM_USED.fetch_sub(job.mem, Ordering::SeqCst);
         let end_result = match worker_result {
             Ok(res) => res,
-            Err(e) => ProcessResult {
-                job,
-                _code: -1,
-                stdout: String::new(),
-                stderr: e.to_string(),
-            },
+            Err(e) => {
+                if let Some(logs) = log_folder {
+                    let err_log = logs.join(format!("{}.err", job.id));
+                    if let Err(write_error) = tokio::fs::write(&err_log, e.to_string()).await {
+                        eprintln!(
+                            "dpr: could not write {}: {}",
+                            err_log.display(),
+                            write_error
+                        );
+                    }
+                }
+                ProcessResult { job, _code: -1 }
+            }
         };
         writer_tx.send(end_result).await?;
         Ok(())
     }
 
-    async fn worker(job: Job, experiment_db: ExperimentDatabase) -> Result<ProcessResult> {
-        // shlex might be necessary
-        let mut s = job.command.split_ascii_whitespace();
-        let cmd = s
-            .next()
-            .ok_or_else(|| anyhow::Error::new(ProcessError::StartProcess))?;
-        let mut child = Command::new(cmd)
-            .args(s)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        GLOBAL_JOB_COUNT.fetch_add(1, Ordering::SeqCst);
-        let res = child.wait().await?;
-        GLOBAL_JOB_COUNT.fetch_sub(1, Ordering::SeqCst);
-        let return_code = ExperimentProcess::process_res(job.id, res, experiment_db).await?;
-        let (stdout, stderr) = ExperimentProcess::process_std(child).await?;
+    async fn worker(
+        job: Job,
+        experiment_db: ExperimentDatabase,
+        options: RunOptions,
+        mut shutdown: Shutdown,
+    ) -> Result<ProcessResult> {
+        let (mut child, stdout_log, stderr_log) =
+            match ExperimentProcess::launch(&job, &options).await {
+                Ok(launched) => launched,
+                Err(e) => {
+                    experiment_db
+                        .record_launch_error(&job, e.to_string(), &options.retry)
+                        .await?;
+                    return Err(e);
+                }
+            };
+        let start_time = SystemTime::now();
+        let start = Instant::now();
+        // Both pipes are read while the child runs, so it never blocks on a
+        // full pipe.
+        let stdout_task = task::spawn(ExperimentProcess::drain(
+            ChildStdout::from_std(child.stdout.take().ok_or(ProcessError::FetchOutput)?)?,
+            stdout_log,
+        ));
+        let stderr_task = task::spawn(ExperimentProcess::drain(
+            ChildStderr::from_std(child.stderr.take().ok_or(ProcessError::FetchOutput)?)?,
+            stderr_log,
+        ));
+        let pid = child.id();
+        let mut wait_task = task::spawn_blocking(move || wait_job(pid));
+        let time_limit = options.time_limit(&job);
+        let mut timed_out = false;
+        let mut interrupted = None;
+        let waited = tokio::select! {
+            waited = &mut wait_task => Some(waited),
+            _ = sleep_for(time_limit) => {
+                timed_out = true;
+                None
+            }
+            signal = shutdown.requested() => {
+                // The job gets the signal the runner received, and some time
+                // to exit cleanly.
+                interrupted = Some(signal);
+                signal_job(pid, signal)?;
+                time::timeout(options.grace_period, &mut wait_task).await.ok()
+            }
+        };
+        let (res, usage) = match waited {
+            Some(waited) => waited??,
+            None => {
+                signal_job(pid, libc::SIGKILL)?;
+                wait_task.await??
+            }
+        };
+        let runtime = start.elapsed();
+        // A job stopped by a shutdown did not get to finish its attempt, so
+        // it is made available again without counting it. One which exited
+        // on its own during the grace period keeps its result, unless it only
+        // reported the signal with its exit code, as shells do.
+        let interrupted = interrupted
+            .filter(|&signal| res.signal().is_some() || res.code() == Some(128 + signal));
+        let recorded = async {
+            experiment_db
+                .record_run_stats(
+                    &job,
+                    RunStats {
+                        exit_code: res.code(),
+                        signal: res.signal(),
+                        start_time: unix_timestamp(start_time),
+                        end_time: unix_timestamp(SystemTime::now()),
+                        wall_time: Some(runtime.as_secs_f64()),
+                        user_time: Some(timeval_secs(usage.ru_utime)),
+                        sys_time: Some(timeval_secs(usage.ru_stime)),
+                        max_rss: Some(usage.ru_maxrss),
+                        launch_error: None,
+                    },
+                )
+                .await?;
+            if let Some(signal) = interrupted {
+                experiment_db.release_job(&job).await?;
+                Ok((128 + signal, None))
+            } else if timed_out {
+                let requeue = experiment_db
+                    .record_timeout(&job, options.timeout_ladder(&job), &options.retry)
+                    .await?;
+                Ok((TIMEOUT_RETURN_CODE, requeue))
+            } else {
+                ExperimentProcess::process_res(&job, res, &experiment_db, &options.retry).await
+            }
+        }
+        .await;
+        // The job was reclaimed while it ran, so its result is left to the
+        // runner which has it now.
+        let (return_code, requeue, lost_lease) = match recorded {
+            Ok((return_code, requeue)) => (return_code, requeue, false),
+            Err(e) if e.is::<LostLease>() => (-1, None, true),
+            Err(e) => return Err(e),
+        };
+        let (stdout, _) = stdout_task.await??;
+        let (mut stderr, mut stderr_log) = stderr_task.await??;
+        let retry = match requeue {
+            Some(Requeue::Retry) => format!(
+                "attempt {} of {}, to be retried{}",
+                job.attempts + 1,
+                options.retry.max_attempts.unwrap_or_default(),
+                match options.retry.backoff {
+                    Some(backoff) => format!(" in {}s", backoff.as_secs()),
+                    None => String::new(),
+                }
+            ),
+            _ => String::new(),
+        };
+        let message = if lost_lease {
+            Some(format!(
+                "dpr: job {} ended after running for {:.1}s but was reclaimed meanwhile as this runner lost its lease, its result was not recorded\n",
+                job.id,
+                runtime.as_secs_f64(),
+            ))
+        } else if interrupted.is_some() {
+            Some(format!(
+                "dpr: job {} stopped after running for {:.1}s as the runner is shutting down, made available again\n",
+                job.id,
+                runtime.as_secs_f64(),
+            ))
+        } else if timed_out {
+            Some(format!(
+                "dpr: job {} killed after running for {:.1}s, over its time limit of {}s{}\n",
+                job.id,
+                runtime.as_secs_f64(),
+                time_limit.unwrap_or_default().as_secs(),
+                match requeue {
+                    Some(Requeue::NextTimeout) => ", requeued with the next timeout".to_owned(),
+                    Some(Requeue::Retry) => format!(", {}", retry),
+                    None => String::new(),
+                }
+            ))
+        } else if requeue.is_some() {
+            Some(format!(
+                "dpr: job {} did not succeed on {}\n",
+                job.id, retry
+            ))
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            append_message(&mut stderr, stderr_log.as_mut(), &message).await?;
+        }
         print!("{}", stdout);
         print!("{}", stderr);
         Ok(ProcessResult {
             job,
             _code: return_code,
-            stdout,
-            stderr,
         })
     }
 
-    async fn process_std(mut child: Child) -> Result<(String, String)> {
-        let mut stdout = String::new();
-        BufReader::new(
-            child
-                .stdout
-                .take()
-                .ok_or_else(|| anyhow::Error::new(ProcessError::FetchOutput))?,
-        )
-        .read_to_string(&mut stdout)
-        .await?;
-        let mut stderr = String::new();
-        BufReader::new(
-            child
-                .stderr
-                .take()
-                .ok_or_else(|| anyhow::Error::new(ProcessError::FetchOutput))?,
-        )
-        .read_to_string(&mut stderr)
-        .await?;
-        Ok((stdout, stderr))
+    /// Opens the log files of the job and starts its command with piped
+    /// output, in its working directory and environment and in a new process
+    /// group.
+    async fn launch(
+        job: &Job,
+        options: &RunOptions,
+    ) -> Result<(Child, Option<File>, Option<File>)> {
+        let (stdout_log, stderr_log) =
+            ExperimentProcess::open_logs(job.id, options.log_folder.as_deref()).await?;
+        let mut groups = JOB_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+        let mut command = ExperimentProcess::command(&job.command, options.shell)?;
+        if let Some(cwd) = &job.cwd {
+            command.current_dir(cwd);
+        }
+        let child = command
+            .envs(job.env.iter().map(|(name, value)| (name, value)))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+        groups.push(child.id());
+        Ok((child, stdout_log, stderr_log))
     }
 
-    async fn process_res(
+    /// Runs the command through `sh -c` in shell mode. Otherwise it is split
+    /// into arguments following POSIX shell quoting and executed directly.
+    fn command(command: &str, shell: bool) -> Result<Command> {
+        if shell {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(command);
+            return Ok(cmd);
+        }
+        let args = shlex::split(command).ok_or(ProcessError::ParseCommand)?;
+        let (program, args) = args.split_first().ok_or(ProcessError::StartProcess)?;
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        Ok(cmd)
+    }
+
+    async fn open_logs(
         job_id: usize,
+        log_folder: Option<&Path>,
+    ) -> Result<(Option<File>, Option<File>)> {
+        match log_folder {
+            Some(logs) => Ok((
+                Some(File::create(logs.join(format!("{}.out", job_id))).await?),
+                Some(File::create(logs.join(format!("{}.err", job_id))).await?),
+            )),
+            None => Ok((None, None)),
+        }
+    }
+
+    /// Copies an output stream of the child to its log file as it comes,
+    /// keeping only its last `OUTPUT_MEMORY_LIMIT` bytes in memory. Returns
+    /// the kept output and the log file.
+    async fn drain(
+        mut stream: impl AsyncRead + Unpin,
+        mut log: Option<File>,
+    ) -> Result<(String, Option<File>)> {
+        let mut kept = Vec::new();
+        let mut dropped = 0;
+        let mut buf = [0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            if let Some(log) = log.as_mut() {
+                log.write_all(&buf[..n]).await?;
+            }
+            kept.extend_from_slice(&buf[..n]);
+            // Trimmed in batches so bytes are not shifted on every read.
+            if kept.len() > 2 * OUTPUT_MEMORY_LIMIT {
+                let excess = kept.len() - OUTPUT_MEMORY_LIMIT;
+                kept.drain(..excess);
+                dropped += excess;
+            }
+        }
+        if kept.len() > OUTPUT_MEMORY_LIMIT {
+            let excess = kept.len() - OUTPUT_MEMORY_LIMIT;
+            kept.drain(..excess);
+            dropped += excess;
+        }
+        if let Some(log) = log.as_mut() {
+            log.flush().await?;
+        }
+        let mut output = String::new();
+        if dropped > 0 {
+            output.push_str(&format!(
+                "dpr: first {} bytes of output not shown, see --log-folder for all of it\n",
+                dropped
+            ));
+        }
+        output.push_str(&String::from_utf8_lossy(&kept));
+        Ok((output, log))
+    }
+
+    async fn process_res(
+        job: &Job,
         res: ExitStatus,
-        experiment_db: ExperimentDatabase,
-    ) -> Result<i32> {
-        if res.success() {
-            experiment_db
-                .change_status_given_ids(vec![job_id], ExperimentStatus::SuccessFinished)
-                .await?;
-        } else if res.code().filter(|&c| c == TIMEOUT_RETURN_CODE).is_some() {
-            experiment_db
-                .change_status_given_ids(vec![job_id], ExperimentStatus::TimedOut)
-                .await?;
+        experiment_db: &ExperimentDatabase,
+        retry: &RetryPolicy,
+    ) -> Result<(i32, Option<Requeue>)> {
+        let status = if res.success() {
+            ExperimentStatus::SuccessFinished
+        } else if res.signal().is_some() {
+            ExperimentStatus::Killed
         } else {
-            experiment_db
-                .change_status_given_ids(vec![job_id], ExperimentStatus::FailedFinished)
-                .await?;
+            ExperimentStatus::FailedFinished
+        };
+        let requeue = experiment_db.finish_attempt(job, status, retry).await?;
+        // Like shells, a job killed by a signal gets 128 plus its number.
+        let code = res
+            .code()
+            .or_else(|| res.signal().map(|signal| 128 + signal))
+            .unwrap_or(-1);
+        Ok((code, requeue))
+    }
+}
+/// Blocks until the child exits, kills the processes it left behind in its
+/// group, and reaps it. The child is only reaped once its group is empty, so
+/// no other process can take its id, which is also the id of the group, in the
+/// meantime.
+fn wait_job(pid: u32) -> io::Result<(ExitStatus, libc::rusage)> {
+    wait_exit(pid)?;
+    {
+        let mut groups = JOB_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+        kill_group(pid, libc::SIGKILL)?;
+        groups.retain(|&group| group != pid);
+    }
+    wait4(pid)
+}
+
+/// Waits for the child to exit, leaving it to be reaped.
+fn wait_exit(pid: u32) -> io::Result<()> {
+    // SAFETY: siginfo_t is a plain C struct, for which all zeroes is valid.
+    let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+    loop {
+        // SAFETY: waitid only writes to the info it is given.
+        let waited = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if waited >= 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Reaps the child with `wait4`, which unlike `waitpid` reports the resources
+/// it used. Blocks until the child exits.
+fn wait4(pid: u32) -> io::Result<(ExitStatus, libc::rusage)> {
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct, for which all zeroes is valid.
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    loop {
+        // SAFETY: wait4 only writes to the status and usage it is given.
+        if unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) } >= 0 {
+            return Ok((ExitStatus::from_raw(status), usage));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
         }
-        match res.code() {
-            Some(ret) => Ok(ret),
-            _ => Err(anyhow::Error::new(ProcessError::GetReturnCode)),
+    }
+}
+
+/// Identifies this runner in the table, as `{host}-{pid}-{random}`.
+pub(crate) fn worker_id() -> String {
+    let mut host = [0u8; 256];
+    // SAFETY: gethostname writes at most the given length to the buffer.
+    let host = if unsafe { libc::gethostname(host.as_mut_ptr().cast(), host.len()) } == 0 {
+        let len = host.iter().position(|&b| b == 0).unwrap_or(host.len());
+        String::from_utf8_lossy(&host[..len]).into_owned()
+    } else {
+        "unknown".to_owned()
+    };
+    // Worker ids are at most 64 characters long in the workers table.
+    let host: String = host.chars().take(40).collect();
+    format!(
+        "{}-{}-{:08x}",
+        host,
+        std::process::id(),
+        rand::random::<u32>()
+    )
+}
+
+/// Cores available to this runner, which may be fewer than those of the host
+/// under a CPU affinity mask or a cgroup quota.
+pub(crate) fn detect_cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |cpus| cpus.get())
+}
+
+/// Physical memory of the host, in MiB. Unlimited if it cannot be read.
+pub(crate) fn detect_mem() -> u64 {
+    // SAFETY: sysconf has no memory safety requirements.
+    let (pages, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_PHYS_PAGES),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+    if pages <= 0 || page_size <= 0 {
+        return u64::MAX;
+    }
+    (pages as u64).saturating_mul(page_size as u64) / (1024 * 1024)
+}
+
+/// Sleeps for the time limit, or forever if there is none.
+async fn sleep_for(time_limit: Option<Duration>) {
+    match time_limit {
+        Some(limit) => time::sleep(limit).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Adds a message of `dpr` to the kept stderr of a job and to its log.
+async fn append_message(stderr: &mut String, log: Option<&mut File>, message: &str) -> Result<()> {
+    if let Some(log) = log {
+        log.write_all(message.as_bytes()).await?;
+        log.flush().await?;
+    }
+    stderr.push_str(message);
+    Ok(())
+}
+
+/// Kills every job still running, and the processes they started, when the
+/// runner exits without waiting for them.
+pub(crate) fn kill_all_jobs() {
+    let groups = JOB_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+    for &group in groups.iter() {
+        let _ = kill_group(group, libc::SIGKILL);
+    }
+}
+
+/// Sends a signal to the process group of a job, unless it was reaped already.
+/// The lock is held meanwhile, so the job cannot be reaped and its id taken by
+/// another process between the check and the signal.
+fn signal_job(pid: u32, signal: i32) -> io::Result<()> {
+    let groups = JOB_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+    if groups.contains(&pid) {
+        kill_group(pid, signal)?;
+    }
+    Ok(())
+}
+
+/// Sends a signal to the process group of a child, which is reaped only once
+/// the group is empty. It is not an error if the group is already empty.
+fn kill_group(pid: u32, signal: i32) -> io::Result<()> {
+    // SAFETY: kill has no memory safety requirements.
+    if unsafe { libc::kill(-(pid as libc::pid_t), signal) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err);
         }
     }
+    Ok(())
+}
+
+fn timeval_secs(time: libc::timeval) -> f64 {
+    time.tv_sec as f64 + time.tv_usec as f64 / 1e6
 }
+
+fn unix_timestamp(time: SystemTime) -> Option<i64> {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
 #[derive(Error, Debug)]
 enum ProcessError {
-    #[error("Cannot get return code of subprocess")]
-    GetReturnCode,
     #[error("Cannot start subprocess because of command is invalid")]
     StartProcess,
+    #[error("Cannot parse command because of an unterminated quote or escape")]
+    ParseCommand,
     #[error("Cannot get stdout/stderr of the process")]
     FetchOutput,
 }
@@ -146,6 +591,4 @@ enum ProcessError {
 pub struct ProcessResult {
     pub job: Job,
     _code: i32,
-    pub stdout: String,
-    pub stderr: String,
 }
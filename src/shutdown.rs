@@ -0,0 +1,56 @@
+use crate::process;
+
+use anyhow::Result;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+/// Tells the runner and its jobs that SIGINT or SIGTERM was received, so they
+/// stop. A second SIGINT kills the running jobs and exits right away.
+#[derive(Clone, Debug)]
+pub(crate) struct Shutdown {
+    signal: watch::Receiver<Option<i32>>,
+}
+
+impl Shutdown {
+    /// Starts listening for SIGINT and SIGTERM.
+    pub(crate) fn listen() -> Result<Shutdown> {
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let (tx, rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let received = tokio::select! {
+                _ = interrupt.recv() => libc::SIGINT,
+                _ = terminate.recv() => libc::SIGTERM,
+            };
+            eprintln!(
+                "dpr: stopping, running jobs will be made available again (Ctrl-C again to exit now)"
+            );
+            tx.send_replace(Some(received));
+            interrupt.recv().await;
+            eprintln!("dpr: killing running jobs and exiting now");
+            process::kill_all_jobs();
+            std::process::exit(128 + libc::SIGINT);
+        });
+        Ok(Shutdown { signal: rx })
+    }
+
+    /// The signal asking to stop, if one was received.
+    pub(crate) fn signal(&self) -> Option<i32> {
+        *self.signal.borrow()
+    }
+
+    /// Waits until a signal asks to stop, and returns it.
+    pub(crate) async fn requested(&mut self) -> i32 {
+        loop {
+            if let Some(signal) = *self.signal.borrow_and_update() {
+                return signal;
+            }
+            if self.signal.changed().await.is_err() {
+                // The listener never stops, except by exiting the process.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
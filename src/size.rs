@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("Invalid memory size `{0}`, expected a number of MiB or a number followed by M, G or T")]
+pub(crate) struct ParseSizeError(String);
+
+/// Parses memory sizes such as `512`, `512M`, `4G` or `1T` into MiB. A bare
+/// number is a number of MiB.
+pub(crate) fn parse_size(s: &str) -> Result<u64, ParseSizeError> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "M"),
+    };
+    let unit_mib = match unit.trim() {
+        "M" | "m" => 1,
+        "G" | "g" => 1024,
+        "T" | "t" => 1024 * 1024,
+        _ => return Err(ParseSizeError(s.to_owned())),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_mib))
+        .ok_or_else(|| ParseSizeError(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_with_and_without_units() {
+        for (s, mib) in [
+            ("512", 512),
+            ("512M", 512),
+            ("4g", 4096),
+            (" 1 T ", 1024 * 1024),
+            ("0", 0),
+        ] {
+            assert_eq!(parse_size(s).unwrap(), mib, "{}", s);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_numbers_and_units() {
+        for s in ["", "G", "4K", "4GB", "1.5G", "-1", "99999999999999999999T"] {
+            assert!(parse_size(s).is_err(), "{}", s);
+        }
+    }
+}
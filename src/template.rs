@@ -0,0 +1,350 @@
+use std::{fs, path::PathBuf};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum TemplateError {
+    #[error(
+        "Invalid parameter `{0}`, expected name=values with a name made of letters, digits and _"
+    )]
+    InvalidParam(String),
+    #[error("Invalid range `{0}`, expected start..end or start..end:step with start <= end and step > 0")]
+    InvalidRange(String),
+    #[error("Cannot read the values of `{0}`: {1}")]
+    UnreadableValues(String, String),
+    #[error("Parameter `{0}` has no values")]
+    NoValues(String),
+    #[error("Parameter `{0}` is given twice")]
+    DuplicateParam(String),
+    #[error("The template has no `{{{0}}}` placeholder for parameter `{0}`")]
+    UnusedParam(String),
+    #[error("The template uses `{{{0}}}` but no such parameter is given")]
+    MissingParam(String),
+    #[error("Cannot zip parameters with different numbers of values ({0})")]
+    ZipLengths(String),
+    #[error("Value `{0}` cannot be quoted into a command")]
+    UnquotableValue(String),
+}
+
+/// Where the values of a template parameter come from.
+#[derive(Clone, Debug)]
+enum ValueSource {
+    /// `a,b,c`
+    List(Vec<String>),
+    /// `1..10` or `0..100:10`, both ends included.
+    Range { start: i64, end: i64, step: i64 },
+    /// `lines:instances.txt`, one value per non-empty line.
+    Lines(PathBuf),
+    /// `glob:instances/*.cnf`, the matching paths in alphabetical order.
+    Glob(String),
+}
+
+/// A parameter of a command template, given as `name=values`.
+#[derive(Clone, Debug)]
+pub(crate) struct Param {
+    name: String,
+    source: ValueSource,
+}
+
+/// Parses `--param` values: `name=a,b,c`, `name=1..10`, `name=0..100:10`,
+/// `name=lines:path` or `name=glob:pattern`.
+pub(crate) fn parse_param(s: &str) -> Result<Param, TemplateError> {
+    let (name, values) = s
+        .split_once('=')
+        .filter(|(name, _)| is_param_name(name))
+        .ok_or_else(|| TemplateError::InvalidParam(s.to_owned()))?;
+    if values.is_empty() {
+        return Err(TemplateError::NoValues(name.to_owned()));
+    }
+    let source = if let Some(path) = values.strip_prefix("lines:") {
+        ValueSource::Lines(PathBuf::from(path))
+    } else if let Some(pattern) = values.strip_prefix("glob:") {
+        ValueSource::Glob(pattern.to_owned())
+    } else if let Some((start, end)) = values.split_once("..") {
+        let (end, step) = end.split_once(':').unwrap_or((end, "1"));
+        match (start.parse(), end.parse(), step.parse()) {
+            (Ok(start), Ok(end), Ok(step)) if start <= end && step > 0 => {
+                ValueSource::Range { start, end, step }
+            }
+            _ => return Err(TemplateError::InvalidRange(values.to_owned())),
+        }
+    } else {
+        ValueSource::List(values.split(',').map(str::to_owned).collect())
+    };
+    Ok(Param {
+        name: name.to_owned(),
+        source,
+    })
+}
+
+fn is_param_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Param {
+    fn values(&self) -> Result<Vec<String>, TemplateError> {
+        let unreadable = |e: &dyn std::fmt::Display| {
+            TemplateError::UnreadableValues(self.name.clone(), e.to_string())
+        };
+        let values: Vec<String> = match &self.source {
+            ValueSource::List(values) => values.clone(),
+            ValueSource::Range { start, end, step } => (*start..=*end)
+                .step_by(*step as usize)
+                .map(|n| n.to_string())
+                .collect(),
+            ValueSource::Lines(path) => fs::read_to_string(path)
+                .map_err(|e| unreadable(&e))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            ValueSource::Glob(pattern) => glob::glob(pattern)
+                .map_err(|e| unreadable(&e))?
+                .map(|path| {
+                    path.map(|p| p.to_string_lossy().into_owned())
+                        .map_err(|e| unreadable(&e))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        if values.is_empty() {
+            return Err(TemplateError::NoValues(self.name.clone()));
+        }
+        Ok(values)
+    }
+}
+
+/// A command expanded from a template, with the value of each parameter.
+pub(crate) type Expansion = (String, Vec<(String, String)>);
+
+/// Expands a command template such as `solver --seed {seed} {file}` into one
+/// command per combination of parameter values, along with the values it got.
+/// Combinations are the cartesian product of the values, the first parameter
+/// varying the slowest, or with `zip` the n-th values of every parameter.
+/// Values are quoted as needed so they stay single arguments.
+pub(crate) fn expand(
+    template: &str,
+    params: &[Param],
+    zip: bool,
+) -> Result<Vec<Expansion>, TemplateError> {
+    let placeholders = placeholders(template);
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].iter().any(|p| p.name == param.name) {
+            return Err(TemplateError::DuplicateParam(param.name.clone()));
+        }
+        if !placeholders.contains(&param.name.as_str()) {
+            return Err(TemplateError::UnusedParam(param.name.clone()));
+        }
+    }
+    if let Some(name) = placeholders
+        .iter()
+        .find(|name| !params.iter().any(|p| p.name == **name))
+    {
+        return Err(TemplateError::MissingParam(name.to_string()));
+    }
+    let values = params
+        .iter()
+        .map(Param::values)
+        .collect::<Result<Vec<_>, _>>()?;
+    let combinations: Vec<Vec<&String>> = if zip {
+        let len = values.first().map_or(1, Vec::len);
+        if values.iter().any(|v| v.len() != len) {
+            let lengths = params
+                .iter()
+                .zip(&values)
+                .map(|(p, v)| format!("{}: {}", p.name, v.len()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(TemplateError::ZipLengths(lengths));
+        }
+        (0..len)
+            .map(|i| values.iter().map(|v| &v[i]).collect())
+            .collect()
+    } else {
+        values
+            .iter()
+            .fold(vec![vec![]], |combinations, param_values| {
+                combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        param_values.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push(value);
+                            combination
+                        })
+                    })
+                    .collect()
+            })
+    };
+    combinations
+        .into_iter()
+        .map(|combination| {
+            let command = fill(template, |name| {
+                let i = params.iter().position(|p| p.name == name)?;
+                Some(
+                    shlex::try_quote(combination[i])
+                        .map_err(|_| TemplateError::UnquotableValue(combination[i].clone())),
+                )
+            })?;
+            let values = params
+                .iter()
+                .zip(combination)
+                .map(|(param, value)| (param.name.clone(), value.clone()))
+                .collect();
+            Ok((command, values))
+        })
+        .collect()
+}
+
+/// Names of the `{name}` placeholders of a template, in order of first use.
+/// Braces around anything else are left as they are.
+fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rest = &rest[open + 1..];
+        if let Some(name) = rest.split_once('}').map(|(name, _)| name) {
+            if is_param_name(name) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+/// Replaces the placeholders of a template in a single pass, so values are
+/// never taken for placeholders themselves.
+fn fill<'a, E>(
+    template: &str,
+    value: impl Fn(&str) -> Option<Result<std::borrow::Cow<'a, str>, E>>,
+) -> Result<String, E> {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        command.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let placeholder = rest[1..]
+            .split_once('}')
+            .and_then(|(name, _)| Some((name, value(name)?)));
+        match placeholder {
+            Some((name, quoted)) => {
+                command.push_str(&quoted?);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                command.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    command.push_str(rest);
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(specs: &[&str]) -> Vec<Param> {
+        specs.iter().map(|s| parse_param(s).unwrap()).collect()
+    }
+
+    fn commands(template: &str, specs: &[&str], zip: bool) -> Vec<String> {
+        expand(template, &params(specs), zip)
+            .unwrap()
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect()
+    }
+
+    #[test]
+    fn expands_every_combination_of_values() {
+        assert_eq!(
+            commands(
+                "solve {file} --seed {seed}",
+                &["seed=1..3:2", "file=a,b c"],
+                false
+            ),
+            [
+                "solve a --seed 1",
+                "solve 'b c' --seed 1",
+                "solve a --seed 3",
+                "solve 'b c' --seed 3"
+            ]
+        );
+        assert_eq!(
+            commands("solve {file} {seed}", &["file=a,b", "seed=1..2"], true),
+            ["solve a 1", "solve b 2"]
+        );
+        let expansions = expand("echo {n}", &params(&["n=7"]), false).unwrap();
+        assert_eq!(
+            expansions,
+            [("echo 7".to_owned(), vec![("n".to_owned(), "7".to_owned())])]
+        );
+    }
+
+    #[test]
+    fn leaves_other_braces_as_they_are() {
+        assert_eq!(
+            commands("awk '{print $1}' {n} {", &["n=1,2"], false),
+            ["awk '{print $1}' 1 {", "awk '{print $1}' 2 {"]
+        );
+        assert_eq!(
+            commands("echo {n} {n}", &["n={n}"], false),
+            ["echo '{n}' '{n}'"]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_params() {
+        for spec in [
+            "seed",
+            "=1,2",
+            "se-ed=1",
+            "seed=3..1",
+            "seed=1..5:0",
+            "seed=a..b",
+        ] {
+            assert!(parse_param(spec).is_err(), "{}", spec);
+        }
+        assert!(matches!(
+            parse_param("seed="),
+            Err(TemplateError::NoValues(name)) if name == "seed"
+        ));
+    }
+
+    #[test]
+    fn rejects_params_not_matching_the_template() {
+        assert!(matches!(
+            expand("echo {n", &params(&["n=1"]), false),
+            Err(TemplateError::UnusedParam(name)) if name == "n"
+        ));
+        assert!(matches!(
+            expand("echo {n} {m}", &params(&["n=1"]), false),
+            Err(TemplateError::MissingParam(name)) if name == "m"
+        ));
+        assert!(matches!(
+            expand("echo {n}", &params(&["n=1", "n=2"]), false),
+            Err(TemplateError::DuplicateParam(name)) if name == "n"
+        ));
+        assert!(matches!(
+            expand("echo {n} {m}", &params(&["n=1,2", "m=1"]), true),
+            Err(TemplateError::ZipLengths(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_files_without_values() {
+        let path = std::env::temp_dir().join(format!("dpr-{}-values.txt", std::process::id()));
+        fs::write(&path, "\n  \n").unwrap();
+        let result = expand(
+            "echo {file}",
+            &params(&[&format!("file=lines:{}", path.display())]),
+            false,
+        );
+        fs::remove_file(path).unwrap();
+        assert!(matches!(result, Err(TemplateError::NoValues(name)) if name == "file"));
+    }
+}
@@ -0,0 +1,9 @@
+$1 -c $2 edit --create-table --legacy-schema 1 --load ../simple.txt
+$1 -c $2 edit --migrate
+$1 -c $2 edit --migrate
+$1 -c $2 run --freq 1 > /dev/null
+$1 -c $2 show --stats
+$1 -c $2 edit --create-table --legacy-schema 10 --load ../simple_2.txt
+$1 -c $2 edit --migrate
+$1 -c $2 run --freq 1 > /dev/null
+$1 -c $2 show --stats
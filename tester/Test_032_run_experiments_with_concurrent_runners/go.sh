@@ -0,0 +1,9 @@
+rm -f .jobfile
+$1 -c $2 edit --create-table --load ../concurrent.txt
+for runner in 1 2 3; do
+    $1 -c $2 run --freq 1 --jobs 4 > /dev/null &
+done
+wait
+echo "Jobs run: $(wc -l < .jobfile)"
+echo "Jobs run more than once: $(sort -n .jobfile | uniq -d | wc -l)"
+$1 -c $2 show --stats
@@ -0,0 +1,6 @@
+$1 -c $2 edit --create-table --load ../quoted.txt
+$1 -c $2 run --freq 1 >> /dev/null
+$1 -c $2 show --all
+$1 -c $2 edit --create-table --set-shell true --load ../simple_2.txt
+$1 -c $2 run --freq 1 >> /dev/null
+$1 -c $2 show --all
@@ -0,0 +1,6 @@
+$1 -c $2 edit --create-table --load ../timeouts.txt
+$1 -c $2 run --freq 1 --jobs 4 --timeout 3s --log-folder logs >> /dev/null
+grep -c "over its time limit" logs/*.err
+rm -rf logs
+$1 -c $2 show --stats
+$1 -c $2 show --all
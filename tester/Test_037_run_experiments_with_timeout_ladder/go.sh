@@ -0,0 +1,6 @@
+$1 -c $2 edit --create-table --set-timeout-ladder 1s,2s,4s --load ../ladder.txt
+$1 -c $2 run --freq 1 --jobs 4 --log-folder logs >> /dev/null
+grep -o "over its time limit.*" logs/4.err
+rm -rf logs
+$1 -c $2 show --stats
+$1 -c $2 show --all
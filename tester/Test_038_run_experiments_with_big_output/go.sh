@@ -0,0 +1,9 @@
+$1 -c $2 edit --create-table --load ../big_output.txt
+timeout 20 $1 -c $2 run --freq 1 --jobs 2 --log-folder logs > output.log
+echo $?
+wc -c < output.log
+wc -c logs/1.out logs/1.err logs/2.out logs/2.err
+tail -n 1 logs/1.out
+grep -c "bytes of output not shown" output.log
+rm -rf logs output.log
+$1 -c $2 show --all
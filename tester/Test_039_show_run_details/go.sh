@@ -0,0 +1,4 @@
+$1 -c $2 edit --create-table --load ../exit_codes.txt
+$1 -c $2 show --all --details
+$1 -c $2 run --freq 1 --jobs 5 --timeout 1 >> /dev/null
+$1 -c $2 show --all --details | cut -d, -f1,3,4,5,6
@@ -0,0 +1,8 @@
+$1 -c $2 edit --create-table --load ../launch_errors.txt
+$1 -c $2 run --freq 1 --jobs 4 >> /dev/null
+$1 -c $2 show --stats
+$1 -c $2 show --all --details | cut -d, -f1,3,4,5,6,13
+$1 -c $2 edit --reset-killed
+$1 -c $2 show --stats
+$1 -c $2 edit --reset-launch-error
+$1 -c $2 show --stats
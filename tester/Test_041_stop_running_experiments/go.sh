@@ -0,0 +1,14 @@
+$1 -c $2 edit --create-table --load ../sleeps.txt
+$1 -c $2 run --freq 1 --jobs 2 --cpus 2 --grace-period 1s > /dev/null 2>&1 &
+sleep 2
+kill -TERM $!
+wait
+$1 -c $2 show --stats
+$1 -c $2 show --all --details | cut -d, -f1,3,4,5,6
+$1 -c $2 edit --create-table --load ../shutdown.txt
+$1 -c $2 run --freq 1 --jobs 4 --cpus 4 --grace-period 1s > /dev/null 2>&1 &
+sleep 2
+kill -TERM $!
+wait
+$1 -c $2 show --stats
+$1 -c $2 show --all --details | cut -d, -f1,3,4,5,6
@@ -0,0 +1,21 @@
+$1 -c $2 edit --create-table --load ../lost.txt
+$1 -c $2 run --freq 1 --lease 2s > /dev/null 2>&1 &
+sleep 1
+kill -9 $!
+wait
+$1 -c $2 show --stats
+sleep 4
+$1 -c $2 run --freq 1 2>&1
+$1 -c $2 show --stats
+$1 -c $2 edit --create-table --load ../lost.txt
+$1 -c $2 run --freq 1 --lease 2s > .jobfile 2>&1 &
+runner=$!
+sleep 1
+kill -STOP $runner
+sleep 3
+$1 -c $2 run --freq 1 > /dev/null 2>&1 &
+sleep 1
+kill -CONT $runner
+wait
+grep -o "was reclaimed meanwhile.*" .jobfile
+$1 -c $2 show --all --details | cut -d, -f1,3,4,5
@@ -0,0 +1,4 @@
+$1 -c $2 edit --create-table --set-max-attempts 3 --set-retry-on failed,killed --set-retry-backoff 1s --load ../retries.txt
+$1 -c $2 run --freq 1 --jobs 3 2>&1 | grep -o "did not succeed.*" | sort
+$1 -c $2 show --stats
+$1 -c $2 show --all
@@ -0,0 +1,5 @@
+$1 -c $2 edit --create-table --load ../process_trees.txt
+$1 -c $2 run --freq 1 --jobs 2 --timeout 2s > /dev/null 2>&1
+sleep 1
+pgrep -c -f "sleep 3[78]" || true
+$1 -c $2 show --all
@@ -0,0 +1,5 @@
+$1 -c $2 edit --create-table --load ../resources.txt
+$1 -c $2 run --freq 1 --jobs 8 --cpus 4 --mem 3G > /dev/null
+awk '{ cpus += $1; mem += $2; if (cpus > max_cpus) max_cpus = cpus; if (mem > max_mem) max_mem = mem }
+    END { print "Most cores used: " max_cpus; print "Most memory used: " max_mem }' .jobfile
+$1 -c $2 show --all
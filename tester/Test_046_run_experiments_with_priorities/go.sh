@@ -0,0 +1,5 @@
+$1 -c $2 edit --create-table --load ../priorities.txt
+$1 -c $2 edit --set-priority 10 --matching "low 3"
+$1 -c $2 edit --set-priority 20 --matching "LOW 2"
+$1 -c $2 edit --set-priority -1 --ids 1
+$1 -c $2 run --freq 1
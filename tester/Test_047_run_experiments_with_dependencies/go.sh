@@ -0,0 +1,13 @@
+rm -f ready.flag
+$1 -c $2 edit --create-table --load ../dependencies.txt
+$1 -c $2 edit --load ../dependencies_2.txt
+$1 -c $2 edit --load ../dependency_cycle.txt 2> /dev/null || echo "Cycle rejected"
+$1 -c $2 show --dependencies
+$1 -c $2 run --freq 1 2> /dev/null
+$1 -c $2 show --dependencies
+$1 -c $2 show --stats
+touch ready.flag
+$1 -c $2 edit --reset-failed
+$1 -c $2 run --freq 1
+$1 -c $2 show --dependencies
+rm -f ready.flag
@@ -0,0 +1,7 @@
+$1 -c $2 edit --create-table --load ../tags.txt
+$1 -c $2 run --freq 1 --tag small --exclude-tag sat
+$1 -c $2 show --stats --tag small
+$1 -c $2 run --freq 1 --tag sat
+$1 -c $2 show --stats --exclude-tag sat
+$1 -c $2 edit --reset-all --tag small,sat
+$1 -c $2 show --stats
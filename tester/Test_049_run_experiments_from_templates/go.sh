@@ -0,0 +1,6 @@
+$1 -c $2 edit --create-table --load-template 'echo {algo} --seed {seed} {size}' --param algo=ga,sa --param seed=1..5:2 --param size=lines:../template_values.txt
+$1 -c $2 edit --load-template 'echo {file} {run}' --param file=glob:'../dependenc*.txt' --param run=1..3 --zip
+$1 -c $2 show --all
+$1 -c $2 run --freq 1
+$1 -c $2 show --group-by algo | cut -d, -f1-9
+$1 -c $2 show --group-by size | cut -d, -f1-9
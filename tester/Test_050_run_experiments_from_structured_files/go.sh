@@ -0,0 +1,7 @@
+$1 -c $2 edit --create-table --load ../structured.csv
+$1 -c $2 edit --load ../structured.jsonl
+$1 -c $2 edit --load ../structured.toml
+$1 -c $2 edit --load ../structured_jsonl.txt --format jsonl
+$1 -c $2 show --dependencies
+$1 -c $2 run --freq 1
+$1 -c $2 show --stats --tag jsonl
@@ -0,0 +1,6 @@
+$1 -c $2 edit --create-table --load ../duplicates.txt
+$1 -c $2 edit --load ../duplicates.txt 2> /dev/null || echo "Duplicates rejected"
+$1 -c $2 edit --load ../duplicates_2.txt --on-duplicate skip
+$1 -c $2 run --freq 1 2> /dev/null
+$1 -c $2 edit --load ../duplicates_2.txt --on-duplicate requeue
+$1 -c $2 show --all
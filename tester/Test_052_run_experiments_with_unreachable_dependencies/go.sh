@@ -0,0 +1,8 @@
+$1 -c $2 edit --create-table --load ../unreachable.txt
+timeout 10 $1 -c $2 run --freq 1 --tag b --cpus 4
+echo "Exit status: $?"
+timeout 10 $1 -c $2 run --freq 1 --exclude-tag b --cpus 4
+echo "Exit status: $?"
+timeout 10 $1 -c $2 run --freq 1 --tag b --cpus 4
+echo "Exit status: $?"
+$1 -c $2 show --all
@@ -0,0 +1,5 @@
+$1 -c $2 edit --create-table --legacy-schema 14 --load ../duplicates.txt
+$1 -c $2 edit --migrate
+$1 -c $2 edit --load ../duplicates.txt 2> /dev/null || echo "Duplicates rejected"
+$1 -c $2 edit --load ../duplicates_2.txt --on-duplicate skip
+$1 -c $2 show --all
@@ -0,0 +1,4 @@
+$1 -c $2 edit --create-table --load ../fitting.txt
+$1 -c $2 run --freq 3 --jobs 2 --cpus 4 > /dev/null
+head -2 .jobfile | sort
+$1 -c $2 show --all
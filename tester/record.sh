@@ -0,0 +1,2 @@
+echo "$1" >> .jobfile
+sleep 0.2
@@ -0,0 +1,3 @@
+echo "$1 $2" >> .jobfile
+sleep 1
+echo "-$1 -$2" >> .jobfile